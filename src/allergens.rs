@@ -0,0 +1,98 @@
+/// The 14 EU regulated allergens, in bit order: bit `i` of a catalog
+/// allergen mask is set when the product has `ALLERGEN_BITS[i]`.
+pub const ALLERGEN_BITS: [&str; 14] = [
+    "gluten",
+    "crustaceans",
+    "eggs",
+    "fish",
+    "peanuts",
+    "soybeans",
+    "milk",
+    "nuts",
+    "celery",
+    "mustard",
+    "sesame-seeds",
+    "sulphur-dioxide-and-sulphites",
+    "lupin",
+    "molluscs",
+];
+
+/// Parses a comma separated OFF tag column ("en:milk, en:nuts") into
+/// lowercase, hyphenated tags without the `en:` prefix.
+///
+/// Tags in other languages keep their prefix ("fr:lait"), since they haven't
+/// been mapped to the English taxonomy.
+pub fn parse_tags(raw: &str) -> Vec<String> {
+    let mut tags: Vec<String> = Vec::new();
+    for tag in raw.split(',') {
+        let tag = tag.trim().to_lowercase();
+        let tag = tag.strip_prefix("en:").unwrap_or(&tag);
+        let tag = tag.split_whitespace().collect::<Vec<_>>().join("-");
+        if !tag.is_empty() && !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    tags
+}
+
+/// Packs allergen tags into a bitmask over `ALLERGEN_BITS`; unknown tags are ignored.
+pub fn allergen_mask(tags: &[String]) -> u16 {
+    tags.iter()
+        .filter_map(|tag| allergen_bit(tag))
+        .fold(0, |mask, bit| mask | (1 << bit))
+}
+
+fn allergen_bit(tag: &str) -> Option<usize> {
+    // Older records carry free text instead of taxonomy tags
+    let canonical = match tag {
+        "wheat" | "barley" | "rye" | "oats" | "spelt" => "gluten",
+        "crustacean" | "shellfish" => "crustaceans",
+        "egg" => "eggs",
+        "peanut" => "peanuts",
+        "soy" | "soya" | "soja" => "soybeans",
+        "lactose" => "milk",
+        "tree-nuts" | "hazelnuts" | "almonds" | "walnuts" | "cashews" => "nuts",
+        "sesame" => "sesame-seeds",
+        "sulphites" | "sulfites" | "sulfur-dioxide-and-sulfites" => "sulphur-dioxide-and-sulphites",
+        "lupine" => "lupin",
+        "mollusc" | "mollusks" => "molluscs",
+        other => other,
+    };
+    ALLERGEN_BITS.iter().position(|&name| name == canonical)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_and_dedupes_tags() {
+        assert_eq!(parse_tags("en:Milk, en:tree nuts,en:milk,fr:lait,, "), ["milk", "tree-nuts", "fr:lait"]);
+        assert!(parse_tags("").is_empty());
+    }
+
+    #[test]
+    fn mask_bits_follow_allergen_bits_order() {
+        assert_eq!(allergen_mask(&parse_tags("en:gluten")), 1);
+        assert_eq!(allergen_mask(&parse_tags("en:milk")), 1 << 6);
+        assert_eq!(allergen_mask(&parse_tags("en:molluscs")), 1 << 13);
+        assert_eq!(allergen_mask(&parse_tags("en:gluten,en:eggs,en:sesame-seeds")), 0b100_0000_0101);
+        for (bit, name) in ALLERGEN_BITS.iter().enumerate() {
+            assert_eq!(allergen_mask(&[name.to_string()]), 1 << bit, "{}", name);
+        }
+    }
+
+    #[test]
+    fn free_text_synonyms_share_the_canonical_bit() {
+        assert_eq!(allergen_mask(&parse_tags("en:wheat,en:oats")), 1);
+        assert_eq!(allergen_mask(&parse_tags("en:soya")), allergen_mask(&parse_tags("en:soybeans")));
+        assert_eq!(allergen_mask(&parse_tags("en:sulfites")), 1 << 11);
+        assert_eq!(allergen_mask(&parse_tags("en:hazelnuts,en:almonds")), 1 << 7);
+    }
+
+    #[test]
+    fn unknown_and_untranslated_tags_set_no_bits() {
+        assert_eq!(allergen_mask(&parse_tags("en:none,fr:lait,en:kiwi")), 0);
+        assert_eq!(allergen_mask(&[]), 0);
+    }
+}
@@ -0,0 +1,206 @@
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::catalog::CatalogEntry;
+use crate::ensure_dir;
+use crate::text;
+
+// Shards are keyed by both the 2 and the 3 character prefix of every word
+const PREFIX_LENGTHS: [usize; 2] = [2, 3];
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum MatchKind {
+    Name,
+    Brand,
+}
+
+/// A shard candidate; `Ord` sorts the best candidate first.
+struct Ranked {
+    kind: MatchKind,
+    name_len: usize,
+    entry: Arc<CatalogEntry>,
+}
+
+impl Ranked {
+    fn rank_key(&self) -> (MatchKind, usize, &str) {
+        (self.kind, self.name_len, self.entry.code.as_str())
+    }
+}
+
+impl PartialEq for Ranked {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank_key() == other.rank_key()
+    }
+}
+
+impl Eq for Ranked {}
+
+impl PartialOrd for Ranked {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Ranked {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank_key().cmp(&other.rank_key())
+    }
+}
+
+/// Per-country prefix shards, each holding the top-N catalog rows for its prefix.
+pub struct AutocompleteIndex {
+    top_n: usize,
+    // country -> prefix -> max-heap whose top is the worst kept row
+    shards: HashMap<String, HashMap<String, BinaryHeap<Ranked>>>,
+}
+
+impl AutocompleteIndex {
+    pub fn new(top_n: usize) -> Self {
+        Self {
+            top_n,
+            shards: HashMap::new(),
+        }
+    }
+
+    pub fn add(&mut self, country_code: &str, entry: CatalogEntry) {
+        let mut prefixes: HashMap<String, MatchKind> = HashMap::new();
+        let name_tokens = entry.name.as_deref().map(text::tokenize).unwrap_or_default();
+        let brand_tokens = entry.brand.as_deref().map(text::tokenize).unwrap_or_default();
+
+        for (tokens, kind) in [(&name_tokens, MatchKind::Name), (&brand_tokens, MatchKind::Brand)] {
+            for token in tokens {
+                for prefix in token_prefixes(token) {
+                    let best = prefixes.entry(prefix).or_insert(kind);
+                    *best = (*best).min(kind);
+                }
+            }
+        }
+
+        if prefixes.is_empty() {
+            return;
+        }
+
+        let name_len = entry.name.as_deref().map(|n| n.chars().count()).unwrap_or(usize::MAX);
+        let entry = Arc::new(entry);
+        let country_shards = self.shards.entry(country_code.to_string()).or_default();
+
+        for (prefix, kind) in prefixes {
+            let heap = country_shards.entry(prefix).or_default();
+            heap.push(Ranked {
+                kind,
+                name_len,
+                entry: Arc::clone(&entry),
+            });
+            if heap.len() > self.top_n {
+                heap.pop();
+            }
+        }
+    }
+
+    /// Writes `{base_dir}/{country}/{prefix}.json`, one JSON array of catalog rows per shard.
+    pub fn write(self, base_dir: &Path) -> Result<usize> {
+        let shard_counts: Vec<usize> = self
+            .shards
+            .into_par_iter()
+            .map(|(country_code, shards)| -> Result<usize> {
+                let country_dir = base_dir.join(&country_code);
+                ensure_dir(&country_dir)?;
+
+                let shard_count = shards.len();
+                for (prefix, heap) in shards {
+                    let ranked = heap.into_sorted_vec();
+                    let rows: Vec<&CatalogEntry> = ranked.iter().map(|r| r.entry.as_ref()).collect();
+
+                    let shard_path = country_dir.join(format!("{}.json", prefix));
+                    let file = File::create(&shard_path)
+                        .with_context(|| format!("Failed to create autocomplete shard: {:?}", shard_path))?;
+                    let mut writer = BufWriter::new(file);
+                    serde_json::to_writer(&mut writer, &rows)
+                        .with_context(|| format!("Failed to write autocomplete shard: {:?}", shard_path))?;
+                    writer.flush()
+                        .with_context(|| format!("Failed to flush autocomplete shard: {:?}", shard_path))?;
+                }
+                Ok(shard_count)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(shard_counts.iter().sum())
+    }
+}
+
+fn token_prefixes(token: &str) -> impl Iterator<Item = String> + '_ {
+    let char_count = token.chars().count();
+    PREFIX_LENGTHS
+        .iter()
+        .filter(move |&&len| char_count >= len)
+        .map(move |&len| token.chars().take(len).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(code: &str, name: &str, brand: &str) -> CatalogEntry {
+        CatalogEntry {
+            code: code.to_string(),
+            name: Some(name.to_string()),
+            brand: Some(brand.to_string()),
+            country: None,
+            serving_size: None,
+            serving_unit: None,
+            nutrients: Vec::new(),
+            allergens: 0,
+            traces: 0,
+        }
+    }
+
+    /// Codes kept in a shard, best first.
+    fn shard(index: &AutocompleteIndex, country: &str, prefix: &str) -> Vec<String> {
+        let Some(heap) = index.shards.get(country).and_then(|shards| shards.get(prefix)) else {
+            return Vec::new();
+        };
+        let mut ranked: Vec<&Ranked> = heap.iter().collect();
+        ranked.sort();
+        ranked.iter().map(|r| r.entry.code.clone()).collect()
+    }
+
+    #[test]
+    fn shards_on_folded_two_and_three_character_prefixes() {
+        let mut index = AutocompleteIndex::new(5);
+        index.add("fr", entry("1", "Crème brûlée", "X"));
+        assert_eq!(shard(&index, "fr", "cr"), ["1"]);
+        assert_eq!(shard(&index, "fr", "cre"), ["1"]);
+        assert_eq!(shard(&index, "fr", "bru"), ["1"]);
+        assert!(shard(&index, "fr", "crem").is_empty());
+        // One-letter brands are too short for any shard
+        assert!(index.shards["fr"].keys().all(|prefix| prefix.chars().count() >= 2));
+    }
+
+    #[test]
+    fn keeps_the_top_n_with_names_before_brands_then_shorter_names() {
+        let mut index = AutocompleteIndex::new(3);
+        index.add("fr", entry("1", "Chocolat noir intense", "Lindt"));
+        index.add("fr", entry("2", "Tablette", "Chocolaterie"));
+        index.add("fr", entry("3", "Chocolat", "Lindt"));
+        index.add("fr", entry("4", "Chocolat au lait", "Milka"));
+        index.add("fr", entry("5", "Choco", "Milka"));
+        // The brand-only match (2) is the worst and is evicted first
+        assert_eq!(shard(&index, "fr", "cho"), ["5", "3", "4"]);
+    }
+
+    #[test]
+    fn countries_have_separate_shards_and_ties_break_on_code() {
+        let mut index = AutocompleteIndex::new(2);
+        index.add("fr", entry("9", "Thé vert", "A"));
+        index.add("fr", entry("7", "Thé noir", "B"));
+        index.add("de", entry("8", "Thé vert", "A"));
+        assert_eq!(shard(&index, "fr", "th"), ["7", "9"]);
+        assert_eq!(shard(&index, "de", "th"), ["8"]);
+    }
+}
@@ -0,0 +1,135 @@
+use thiserror::Error;
+
+// GTIN lengths we accept: EAN-8, UPC-A, EAN-13, GTIN-14
+const VALID_LENGTHS: [usize; 4] = [8, 12, 13, 14];
+
+#[derive(Debug, Error)]
+pub enum BarcodeError {
+    #[error("barcode is empty")]
+    Empty,
+    #[error("barcode contains non-digit characters")]
+    NonDigit,
+    #[error("barcode has {0} digits, expected 8, 12, 13 or 14")]
+    InvalidLength(usize),
+    #[error("barcode is all zeros")]
+    AllZeros,
+}
+
+/// Strips separators (spaces, dashes) and checks the barcode is a plausible GTIN.
+pub fn sanitize(raw: &str) -> Result<String, BarcodeError> {
+    let cleaned: String = raw.chars().filter(|c| !c.is_whitespace() && *c != '-').collect();
+    if cleaned.is_empty() {
+        return Err(BarcodeError::Empty);
+    }
+    if !cleaned.chars().all(|c| c.is_ascii_digit()) {
+        return Err(BarcodeError::NonDigit);
+    }
+    if !VALID_LENGTHS.contains(&cleaned.len()) {
+        return Err(BarcodeError::InvalidLength(cleaned.len()));
+    }
+    // Placeholder codes in the dump; every padded form would match one of them
+    if cleaned.chars().all(|c| c == '0') {
+        return Err(BarcodeError::AllZeros);
+    }
+    Ok(cleaned)
+}
+
+/// Returns the product file codes to try for a barcode, most likely first.
+///
+/// The processor stores codes as digits exactly as they appear in the OFF dump,
+/// which mixes zero-padded and unpadded forms (a UPC-A may live under its 12 or
+/// 13 digit form, an EAN-8 under 8 or 13 digits). We therefore try the code as
+/// given, then the EAN-13 form, then every other GTIN width of the same number.
+pub fn candidates(code: &str) -> Vec<String> {
+    let core = code.trim_start_matches('0');
+    let mut out: Vec<String> = vec![code.to_string()];
+
+    for width in [13, 12, 14, 8] {
+        if core.len() <= width {
+            push_unique(&mut out, format!("{:0>width$}", core, width = width));
+        }
+    }
+
+    // OFF also holds a few codes stored without any leading zeros
+    if !core.is_empty() {
+        push_unique(&mut out, core.to_string());
+    }
+
+    out
+}
+
+/// Checks the GTIN mod-10 check digit.
+pub fn has_valid_check_digit(code: &str) -> bool {
+    let digits: Vec<u32> = code.chars().filter_map(|c| c.to_digit(10)).collect();
+    let Some((&check, body)) = digits.split_last() else {
+        return false;
+    };
+
+    let sum: u32 = body
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, d)| if i % 2 == 0 { d * 3 } else { *d })
+        .sum();
+
+    (10 - sum % 10) % 10 == check
+}
+
+fn push_unique(out: &mut Vec<String>, code: String) {
+    if !out.contains(&code) {
+        out.push(code);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_strips_separators_and_checks_length() {
+        assert_eq!(sanitize(" 3017-6204 22003 ").unwrap(), "3017620422003");
+        assert_eq!(sanitize("96385074").unwrap(), "96385074");
+        assert_eq!(sanitize("10012345678902").unwrap(), "10012345678902");
+        assert!(matches!(sanitize(" - "), Err(BarcodeError::Empty)));
+        assert!(matches!(sanitize("30176204220O3"), Err(BarcodeError::NonDigit)));
+        assert!(matches!(sanitize("12345"), Err(BarcodeError::InvalidLength(5))));
+        assert!(matches!(sanitize("000000000000"), Err(BarcodeError::AllZeros)));
+        assert!(matches!(sanitize("0000-0000"), Err(BarcodeError::AllZeros)));
+    }
+
+    #[test]
+    fn upc_a_and_ean_13_forms_find_each_other() {
+        assert_eq!(
+            candidates("012345678905"),
+            ["012345678905", "0012345678905", "00012345678905", "12345678905"]
+        );
+        assert_eq!(
+            candidates("0012345678905"),
+            ["0012345678905", "012345678905", "00012345678905", "12345678905"]
+        );
+    }
+
+    #[test]
+    fn ean_8_is_tried_padded_to_every_width() {
+        assert_eq!(
+            candidates("96385074"),
+            ["96385074", "0000096385074", "000096385074", "00000096385074"]
+        );
+    }
+
+    #[test]
+    fn gtin_14_keeps_its_own_form_first() {
+        assert_eq!(candidates("10012345678902"), ["10012345678902"]);
+        assert_eq!(candidates("00012345678905")[..2], ["00012345678905", "0012345678905"]);
+    }
+
+    #[test]
+    fn check_digits() {
+        for valid in ["3017620422003", "012345678905", "96385074", "10012345678902"] {
+            assert!(has_valid_check_digit(valid), "{}", valid);
+        }
+        for invalid in ["3017620422004", "012345678900", "96385075", ""] {
+            assert!(!has_valid_check_digit(invalid), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::paged_index;
+
+/// A brand as it appears in the brand index.
+#[derive(Debug)]
+pub struct Brand {
+    /// URL and directory safe key, e.g. "dr-oetker"
+    pub key: String,
+    /// Display spelling from the record, e.g. "Dr. Oetker"
+    pub name: String,
+}
+
+/// Splits a `brands` value ("Ferrero, Nutella") into canonical brands.
+///
+/// Keys ignore case, accents and punctuation, so "Nestlé", "NESTLE" and
+/// "nestle." all land on `nestle`.
+pub fn canonicalize(raw: &str) -> Vec<Brand> {
+    let mut brands: Vec<Brand> = Vec::new();
+    for part in raw.split(',') {
+        let name = part.split_whitespace().collect::<Vec<_>>().join(" ");
+        let key = paged_index::key_for(&name);
+        if key.is_empty() || brands.iter().any(|b| b.key == key) {
+            continue;
+        }
+        brands.push(Brand { key, name });
+    }
+    brands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(raw: &str) -> Vec<(String, String)> {
+        canonicalize(raw).into_iter().map(|b| (b.key, b.name)).collect()
+    }
+
+    #[test]
+    fn case_accents_and_punctuation_share_a_key() {
+        for raw in ["Nestlé", "NESTLE", "nestle.", "  Nestlé  "] {
+            assert_eq!(canonicalize(raw)[0].key, "nestle", "{:?}", raw);
+        }
+        assert_eq!(canonicalize("L'Oréal")[0].key, "loreal");
+        assert_eq!(canonicalize("Dr.  Oetker")[0].name, "Dr. Oetker");
+    }
+
+    #[test]
+    fn splits_multi_brand_values_and_drops_repeats() {
+        assert_eq!(
+            keys("Ferrero, Nutella,ferrero , ,Kinder Bueno"),
+            [
+                ("ferrero".to_string(), "Ferrero".to_string()),
+                ("nutella".to_string(), "Nutella".to_string()),
+                ("kinder-bueno".to_string(), "Kinder Bueno".to_string()),
+            ]
+        );
+        assert!(canonicalize(" , ... ,").is_empty());
+    }
+}
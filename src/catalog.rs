@@ -0,0 +1,802 @@
+use anyhow::{bail, Context, Result};
+use brotli::enc::BrotliEncoderParams;
+use brotli::CompressorWriter;
+use clap::ValueEnum;
+use serde::ser::{SerializeMap, SerializeSeq};
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::nutrients::{self, Breakdown, NutrientDef, NutrientRegistry, Unit};
+
+const MANIFEST_FILE: &str = "manifest.json";
+const TOP_CATALOG_FILE: &str = "top.jsonl.br";
+const FULL_CATALOG_FILE: &str = "catalog.jsonl.br";
+// Sorted runs of a catalog too big to sort in memory at once
+const SORT_RUN_PREFIX: &str = "catalog.run-";
+
+/// Bumped whenever the meaning of existing columns changes.
+pub const SCHEMA_VERSION: u32 = 1;
+
+static SCHEMA: OnceLock<Schema> = OnceLock::new();
+
+/// How catalog rows are written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RowFormat {
+    /// Positional arrays in header column order
+    Array,
+    /// Objects keyed by column name
+    Object,
+}
+
+/// Named sets of nutrient columns for `--catalog-columns`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// Fiber, carbohydrates, fat and proteins
+    Macros,
+    /// The nutrients of an EU nutrition label
+    Label,
+    /// Every nutrient in the registry
+    Full,
+}
+
+impl Preset {
+    fn nutrients(self, registry: &NutrientRegistry) -> Vec<Cow<'static, str>> {
+        let names: &[&'static str] = match self {
+            Preset::Macros => &["fiber", "carbohydrates", "fat", "proteins"],
+            Preset::Label => &["energy_kcal", "fat", "saturated", "carbohydrates", "sugars", "fiber", "proteins", "salt"],
+            Preset::Full => return registry.defs().iter().map(|def| def.name.clone()).collect(),
+        };
+        names.iter().map(|name| Cow::Borrowed(*name)).collect()
+    }
+}
+
+/// The nutrient columns of the catalog: a preset or a list of nutrient names.
+#[derive(Debug, Clone)]
+pub enum NutrientColumns {
+    Preset(Preset),
+    List(Vec<String>),
+}
+
+/// Parses a `--catalog-columns` argument: `label`, or `energy_kcal,sugars,salt`.
+pub fn parse_columns(raw: &str) -> Result<NutrientColumns, String> {
+    if let Ok(preset) = Preset::from_str(raw.trim(), true) {
+        return Ok(NutrientColumns::Preset(preset));
+    }
+    let names: Vec<String> = raw
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(str::to_string)
+        .collect();
+    if names.is_empty() {
+        return Err("expected a preset or a comma-separated list of nutrients".to_string());
+    }
+    Ok(NutrientColumns::List(names))
+}
+
+// Catalog column names that differ from the nutrient name, kept from the
+// original fixed layout
+const COLUMN_ALIASES: &[(&str, &str)] = &[("carbohydrates", "carbs"), ("proteins", "protein")];
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColumnType {
+    String,
+    Number,
+    Integer,
+}
+
+/// Where a column's value comes from in a `CatalogEntry`.
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Code,
+    Name,
+    Brand,
+    Country,
+    ServingSize,
+    ServingUnit,
+    /// Index into `CatalogEntry::nutrients`
+    Nutrient(usize),
+    Allergens,
+    Traces,
+}
+
+/// A catalog column as described in the catalog header.
+#[derive(Debug, Clone, Serialize)]
+pub struct Column {
+    name: Cow<'static, str>,
+    #[serde(rename = "type")]
+    kind: ColumnType,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<Cow<'static, str>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'static str>,
+    #[serde(skip)]
+    field: Field,
+}
+
+const fn column(name: &'static str, kind: ColumnType, unit: Option<&'static str>, description: Option<&'static str>, field: Field) -> Column {
+    let unit = match unit {
+        Some(unit) => Some(Cow::Borrowed(unit)),
+        None => None,
+    };
+    Column { name: Cow::Borrowed(name), kind, unit, description, field }
+}
+
+const LEADING_COLUMNS: &[Column] = &[
+    column("code", ColumnType::String, None, Some("Barcode"), Field::Code),
+    column("name", ColumnType::String, None, Some("Product name in the country's language when known"), Field::Name),
+    column("brand", ColumnType::String, None, None, Field::Brand),
+    column("country", ColumnType::String, None, Some("ISO 3166-1 alpha-2 code"), Field::Country),
+    column("serving_size", ColumnType::Number, None, Some("In `serving_unit`; 100 when the product has no serving size"), Field::ServingSize),
+    column("serving_unit", ColumnType::String, None, Some("\"g\" or \"ml\""), Field::ServingUnit),
+];
+
+const TRAILING_COLUMNS: &[Column] = &[
+    column("allergens", ColumnType::Integer, Some("bitmask"), Some("Bits of the 14 EU allergens, see README"), Field::Allergens),
+    column("traces", ColumnType::Integer, Some("bitmask"), Some("Same bits as `allergens`"), Field::Traces),
+];
+
+/// The catalog row layout; set once at startup, like the nutrient registry.
+#[derive(Debug)]
+pub struct Schema {
+    pub row_format: RowFormat,
+    columns: Vec<Column>,
+    // Registry entries of the nutrient columns, in column order
+    nutrients: Vec<NutrientDef>,
+}
+
+impl Schema {
+    /// Resolves the nutrient columns against the registry, which must already be installed.
+    pub fn new(row_format: RowFormat, nutrient_columns: &NutrientColumns) -> Result<Self> {
+        Self::with_registry(row_format, nutrient_columns, nutrients::registry())
+    }
+
+    fn with_registry(row_format: RowFormat, nutrient_columns: &NutrientColumns, registry: &NutrientRegistry) -> Result<Self> {
+        let requested = match nutrient_columns {
+            NutrientColumns::Preset(preset) => preset.nutrients(registry),
+            NutrientColumns::List(names) => names.iter().map(|name| Cow::Owned(name.clone())).collect(),
+        };
+
+        let mut columns: Vec<Column> = LEADING_COLUMNS.to_vec();
+        let mut nutrients: Vec<NutrientDef> = Vec::new();
+        for requested in requested {
+            // Aliases are accepted too, so `carbs` works as well as `carbohydrates`
+            let name = COLUMN_ALIASES
+                .iter()
+                .find(|(_, alias)| *alias == requested)
+                .map(|(name, _)| Cow::Borrowed(*name))
+                .unwrap_or(requested);
+            let def = registry
+                .slot(&name)
+                .map(|slot| &registry.defs()[slot])
+                .with_context(|| format!("Unknown catalog column: {:?}", name))?;
+            if nutrients.iter().any(|added| added.name == def.name) {
+                continue;
+            }
+            let column_name = COLUMN_ALIASES
+                .iter()
+                .find(|(nutrient, _)| *nutrient == def.name)
+                .map(|(_, alias)| Cow::Borrowed(*alias))
+                .unwrap_or_else(|| def.name.clone());
+            if columns.iter().chain(TRAILING_COLUMNS).any(|column| column.name == column_name) {
+                bail!("Catalog column {:?} for nutrient {:?} clashes with another column", column_name, def.name);
+            }
+            // Values are published in the display unit, as in the product JSON
+            let unit = match def.published_unit() {
+                Unit::PercentVol => Unit::PercentVol.symbol().to_string(),
+                unit => format!("{}/100g", unit.symbol()),
+            };
+            columns.push(Column {
+                name: column_name,
+                kind: ColumnType::Number,
+                unit: Some(Cow::Owned(unit)),
+                description: None,
+                field: Field::Nutrient(nutrients.len()),
+            });
+            nutrients.push(def.clone());
+        }
+        columns.extend_from_slice(TRAILING_COLUMNS);
+
+        Ok(Self { row_format, columns, nutrients })
+    }
+
+    pub fn column_count(&self) -> usize {
+        self.columns.len()
+    }
+
+    pub fn header(&self) -> Header<'_> {
+        Header {
+            schema_version: SCHEMA_VERSION,
+            row_format: self.row_format,
+            columns: &self.columns,
+        }
+    }
+
+    /// The values of the nutrient columns, for `CatalogEntry::nutrients`.
+    pub fn nutrient_values(&self, breakdown: &Breakdown) -> Vec<Option<f64>> {
+        self.nutrients
+            .iter()
+            .map(|def| breakdown.get(&def.name).map(|value| def.to_published(value)))
+            .collect()
+    }
+}
+
+pub fn init(schema: Schema) {
+    if SCHEMA.set(schema).is_err() {
+        panic!("catalog schema initialized twice");
+    }
+}
+
+pub fn schema() -> &'static Schema {
+    SCHEMA.get_or_init(|| {
+        Schema::new(RowFormat::Array, &NutrientColumns::Preset(Preset::Macros))
+            .expect("macros preset only names built-in nutrients")
+    })
+}
+
+/// First line of every catalog file, describing the rows that follow.
+#[derive(Serialize)]
+pub struct Header<'a> {
+    schema_version: u32,
+    row_format: RowFormat,
+    columns: &'a [Column],
+}
+
+/// Writes the header on its own, for indexes that embed catalog rows without one.
+pub fn write_header_file(path: &Path) -> Result<()> {
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create catalog header: {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, &schema().header())
+        .with_context(|| format!("Failed to write catalog header: {:?}", path))?;
+    writer.flush()
+        .with_context(|| format!("Failed to flush catalog header: {:?}", path))?;
+    Ok(())
+}
+
+#[derive(Debug)]
+pub struct CatalogEntry {
+    pub code: String,
+    pub name: Option<String>,
+    pub brand: Option<String>,
+    pub country: Option<String>,
+    pub serving_size: Option<f64>,
+    pub serving_unit: Option<String>,
+    /// Values of the schema's nutrient columns, in column order
+    pub nutrients: Vec<Option<f64>>,
+    // Bitmasks over `allergens::ALLERGEN_BITS`
+    pub allergens: u16,
+    pub traces: u16,
+}
+
+enum Value<'a> {
+    Text(Option<&'a str>),
+    Number(Option<f64>),
+    Mask(u16),
+}
+
+impl Serialize for Value<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Value::Text(v) => v.serialize(serializer),
+            Value::Number(v) => v.serialize(serializer),
+            Value::Mask(v) => v.serialize(serializer),
+        }
+    }
+}
+
+impl CatalogEntry {
+    fn value(&self, field: Field) -> Value<'_> {
+        match field {
+            Field::Code => Value::Text(Some(&self.code)),
+            Field::Name => Value::Text(self.name.as_deref()),
+            Field::Brand => Value::Text(self.brand.as_deref()),
+            Field::Country => Value::Text(self.country.as_deref()),
+            Field::ServingSize => Value::Number(self.serving_size),
+            Field::ServingUnit => Value::Text(self.serving_unit.as_deref()),
+            Field::Nutrient(i) => Value::Number(self.nutrients.get(i).copied().flatten()),
+            Field::Allergens => Value::Mask(self.allergens),
+            Field::Traces => Value::Mask(self.traces),
+        }
+    }
+}
+
+/// Rows follow the schema's columns and row format, so they always match the header.
+impl Serialize for CatalogEntry {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let schema = schema();
+        match schema.row_format {
+            RowFormat::Array => {
+                let mut seq = serializer.serialize_seq(Some(schema.columns.len()))?;
+                for column in &schema.columns {
+                    seq.serialize_element(&self.value(column.field))?;
+                }
+                seq.end()
+            }
+            RowFormat::Object => {
+                let mut map = serializer.serialize_map(Some(schema.columns.len()))?;
+                for column in &schema.columns {
+                    map.serialize_entry(&column.name, &self.value(column.field))?;
+                }
+                map.end()
+            }
+        }
+    }
+}
+
+/// Sort key of a catalog row; rows without OFF popularity data sort last.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Popularity {
+    pub popularity_key: Option<i64>,
+    pub unique_scans_n: Option<i64>,
+}
+
+impl Popularity {
+    /// Prefix of a row in the unsorted catalog file.
+    pub fn spill_prefix(&self) -> String {
+        let field = |v: Option<i64>| v.map(|v| v.to_string()).unwrap_or_default();
+        format!("{}\t{}", field(self.popularity_key), field(self.unique_scans_n))
+    }
+
+    fn parse_spill_line(line: &str) -> Option<(Self, &str)> {
+        let mut parts = line.splitn(3, '\t');
+        let popularity_key = parts.next()?.parse().ok();
+        let unique_scans_n = parts.next()?.parse().ok();
+        let row = parts.next()?;
+        Some((Self { popularity_key, unique_scans_n }, row))
+    }
+}
+
+/// One independently compressed file of catalog rows, after a header line.
+#[derive(Serialize)]
+struct ChunkInfo {
+    /// Relative to the manifest
+    url: String,
+    first_row: usize,
+    rows: usize,
+    bytes: usize,
+    sha256: String,
+}
+
+#[derive(Serialize)]
+struct Manifest<'a> {
+    country: &'a str,
+    rows: usize,
+    chunk_rows: usize,
+    chunks: Vec<ChunkInfo>,
+    /// The most popular rows, for bootstrapping a client on first launch
+    top: ChunkInfo,
+}
+
+/// Sorts a country's unsorted catalog by popularity and writes its chunks, the
+/// top-N and full catalogs and `manifest.json`. Returns the row count.
+///
+/// At most `sort_run_rows` rows are sorted in memory at once; bigger catalogs
+/// are sorted in runs that are merged back from disk. The previous manifest is
+/// removed first and the new one written last, so a reader never sees a
+/// manifest that points at chunks that aren't there.
+pub fn write_sorted(
+    unsorted_path: &Path,
+    catalog_dir: &Path,
+    country_code: &str,
+    chunk_rows: usize,
+    top_n: usize,
+    sort_run_rows: usize,
+) -> Result<usize> {
+    remove_previous_catalog(catalog_dir)?;
+
+    let (spilled_runs, last_run) = sort_runs(unsorted_path, catalog_dir, sort_run_rows.max(1))?;
+    let mut writer = SortedCatalogWriter::create(catalog_dir, chunk_rows.max(1), top_n)?;
+    let merged = merge_runs(&spilled_runs, last_run, |row| writer.push(row));
+    for path in &spilled_runs {
+        let _ = fs::remove_file(path);
+    }
+    merged?;
+
+    writer.finish(country_code)
+}
+
+/// Removes the manifest, chunks and sort runs left by a previous run, so a
+/// catalog with fewer chunks than last time leaves no orphans behind.
+fn remove_previous_catalog(catalog_dir: &Path) -> Result<()> {
+    let manifest_path = catalog_dir.join(MANIFEST_FILE);
+    if manifest_path.exists() {
+        fs::remove_file(&manifest_path)
+            .with_context(|| format!("Failed to remove catalog manifest: {:?}", manifest_path))?;
+    }
+
+    let entries = fs::read_dir(catalog_dir)
+        .with_context(|| format!("Failed to read catalog directory: {:?}", catalog_dir))?;
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        let is_chunk = name.starts_with("catalog-") && name.ends_with(".jsonl.br");
+        let is_run = name.starts_with(SORT_RUN_PREFIX) && name.ends_with(".tsv");
+        if is_chunk || is_run {
+            fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove stale catalog file: {:?}", path))?;
+        }
+    }
+    Ok(())
+}
+
+type SortRun = Vec<(Popularity, String)>;
+
+/// Splits the unsorted catalog into sorted runs of at most `run_rows` rows.
+/// Every full run is spilled to disk; the last one is returned in memory.
+fn sort_runs(unsorted_path: &Path, catalog_dir: &Path, run_rows: usize) -> Result<(Vec<PathBuf>, SortRun)> {
+    let file = File::open(unsorted_path)
+        .with_context(|| format!("Failed to open unsorted catalog: {:?}", unsorted_path))?;
+
+    let mut spilled = Vec::new();
+    let mut run: SortRun = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("Failed to read unsorted catalog: {:?}", unsorted_path))?;
+        if let Some((popularity, row)) = Popularity::parse_spill_line(&line) {
+            run.push((popularity, row.to_string()));
+        }
+        if run.len() == run_rows {
+            let path = catalog_dir.join(format!("{}{:04}.tsv", SORT_RUN_PREFIX, spilled.len()));
+            write_run(&path, &mut run)?;
+            spilled.push(path);
+            run.clear();
+        }
+    }
+
+    sort_run(&mut run);
+    Ok((spilled, run))
+}
+
+fn sort_run(run: &mut SortRun) {
+    // Stable, so equally popular rows keep CSV order
+    run.sort_by(|(a, _), (b, _)| b.cmp(a));
+}
+
+fn write_run(path: &Path, run: &mut SortRun) -> Result<()> {
+    sort_run(run);
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create catalog sort run: {:?}", path))?;
+    let mut writer = BufWriter::with_capacity(64 * 1024, file);
+    for (popularity, row) in run.iter() {
+        writeln!(writer, "{}\t{}", popularity.spill_prefix(), row)
+            .with_context(|| format!("Failed to write catalog sort run: {:?}", path))?;
+    }
+    writer.flush()
+        .with_context(|| format!("Failed to flush catalog sort run: {:?}", path))?;
+    Ok(())
+}
+
+/// The next row of a sort run; `Ord` puts the most popular first, and the
+/// earlier run first among equals so ties keep CSV order across runs.
+struct RunHead {
+    popularity: Popularity,
+    run: usize,
+    row: String,
+}
+
+impl RunHead {
+    fn rank_key(&self) -> (Popularity, Reverse<usize>) {
+        (self.popularity, Reverse(self.run))
+    }
+}
+
+impl PartialEq for RunHead {
+    fn eq(&self, other: &Self) -> bool {
+        self.rank_key() == other.rank_key()
+    }
+}
+
+impl Eq for RunHead {}
+
+impl PartialOrd for RunHead {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for RunHead {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.rank_key().cmp(&other.rank_key())
+    }
+}
+
+type RunReader = Box<dyn Iterator<Item = Result<(Popularity, String)>>>;
+
+/// Merges the spilled runs and the in-memory last run, handing rows to `emit` most popular first.
+fn merge_runs(spilled: &[PathBuf], last: SortRun, mut emit: impl FnMut(String) -> Result<()>) -> Result<()> {
+    let mut readers: Vec<RunReader> = Vec::with_capacity(spilled.len() + 1);
+    for path in spilled {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open catalog sort run: {:?}", path))?;
+        let path = path.clone();
+        readers.push(Box::new(BufReader::new(file).lines().map(move |line| {
+            let line = line.with_context(|| format!("Failed to read catalog sort run: {:?}", path))?;
+            let (popularity, row) = Popularity::parse_spill_line(&line)
+                .with_context(|| format!("Malformed catalog sort run: {:?}", path))?;
+            Ok((popularity, row.to_string()))
+        })));
+    }
+    readers.push(Box::new(last.into_iter().map(Ok)));
+
+    let mut heads = BinaryHeap::with_capacity(readers.len());
+    for (run, reader) in readers.iter_mut().enumerate() {
+        if let Some(next) = reader.next() {
+            let (popularity, row) = next?;
+            heads.push(RunHead { popularity, run, row });
+        }
+    }
+    while let Some(RunHead { run, row, .. }) = heads.pop() {
+        emit(row)?;
+        if let Some(next) = readers[run].next() {
+            let (popularity, row) = next?;
+            heads.push(RunHead { popularity, run, row });
+        }
+    }
+    Ok(())
+}
+
+/// Takes rows most popular first and writes the chunks, the top-N catalog,
+/// the full catalog and finally the manifest.
+struct SortedCatalogWriter<'a> {
+    catalog_dir: &'a Path,
+    chunk_rows: usize,
+    top_n: usize,
+    rows: usize,
+    chunk: Vec<String>,
+    chunks: Vec<ChunkInfo>,
+    top: Vec<String>,
+    full: CompressorWriter<BufWriter<File>>,
+}
+
+impl<'a> SortedCatalogWriter<'a> {
+    fn create(catalog_dir: &'a Path, chunk_rows: usize, top_n: usize) -> Result<Self> {
+        let full_path = catalog_dir.join(FULL_CATALOG_FILE);
+        let file = File::create(&full_path)
+            .with_context(|| format!("Failed to create catalog: {:?}", full_path))?;
+        let mut full = CompressorWriter::with_params(
+            BufWriter::new(file),
+            64 * 1024,
+            &BrotliEncoderParams::default(),
+        );
+        serde_json::to_writer(&mut full, &schema().header())?;
+        full.write_all(b"\n")?;
+
+        Ok(Self {
+            catalog_dir,
+            chunk_rows,
+            top_n,
+            rows: 0,
+            chunk: Vec::with_capacity(chunk_rows),
+            chunks: Vec::new(),
+            top: Vec::new(),
+            full,
+        })
+    }
+
+    fn push(&mut self, row: String) -> Result<()> {
+        self.full.write_all(row.as_bytes())?;
+        self.full.write_all(b"\n")?;
+        if self.top.len() < self.top_n {
+            self.top.push(row.clone());
+        }
+        self.chunk.push(row);
+        self.rows += 1;
+        if self.chunk.len() == self.chunk_rows {
+            self.write_chunk()?;
+        }
+        Ok(())
+    }
+
+    fn write_chunk(&mut self) -> Result<()> {
+        let file_name = format!("catalog-{:04}.jsonl.br", self.chunks.len() + 1);
+        let first_row = self.rows - self.chunk.len();
+        self.chunks.push(write_chunk(self.catalog_dir, &file_name, first_row, &self.chunk)?);
+        self.chunk.clear();
+        Ok(())
+    }
+
+    fn finish(mut self, country_code: &str) -> Result<usize> {
+        if !self.chunk.is_empty() {
+            self.write_chunk()?;
+        }
+        self.full.flush()?;
+        self.full.into_inner().flush()
+            .with_context(|| format!("Failed to flush catalog: {:?}", self.catalog_dir.join(FULL_CATALOG_FILE)))?;
+        let top = write_chunk(self.catalog_dir, TOP_CATALOG_FILE, 0, &self.top)?;
+
+        let manifest = Manifest {
+            country: country_code,
+            rows: self.rows,
+            chunk_rows: self.chunk_rows,
+            chunks: self.chunks,
+            top,
+        };
+        let manifest_path = self.catalog_dir.join(MANIFEST_FILE);
+        let file = File::create(&manifest_path)
+            .with_context(|| format!("Failed to create catalog manifest: {:?}", manifest_path))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, &manifest)
+            .with_context(|| format!("Failed to write catalog manifest: {:?}", manifest_path))?;
+        writer.flush()
+            .with_context(|| format!("Failed to flush catalog manifest: {:?}", manifest_path))?;
+
+        Ok(self.rows)
+    }
+}
+
+fn write_chunk(catalog_dir: &Path, file_name: &str, first_row: usize, rows: &[String]) -> Result<ChunkInfo> {
+    let mut writer = CompressorWriter::with_params(
+        Vec::new(),
+        64 * 1024,
+        &BrotliEncoderParams::default(),
+    );
+    let header = serde_json::to_string(&schema().header())?;
+    writer.write_all(header.as_bytes())?;
+    writer.write_all(b"\n")?;
+    for row in rows {
+        writer.write_all(row.as_bytes())?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    let compressed = writer.into_inner();
+
+    let path = catalog_dir.join(file_name);
+    fs::write(&path, &compressed)
+        .with_context(|| format!("Failed to write catalog chunk: {:?}", path))?;
+
+    Ok(ChunkInfo {
+        url: file_name.to_string(),
+        first_row,
+        rows: rows.len(),
+        bytes: compressed.len(),
+        sha256: format!("{:x}", Sha256::digest(&compressed)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn column_names(nutrient_columns: &str) -> Vec<String> {
+        let nutrient_columns = parse_columns(nutrient_columns).unwrap();
+        let schema = Schema::new(RowFormat::Array, &nutrient_columns).unwrap();
+        schema.columns.iter().map(|column| column.name.to_string()).collect()
+    }
+
+    #[test]
+    fn macros_preset_keeps_the_original_layout() {
+        assert_eq!(
+            column_names("macros"),
+            [
+                "code", "name", "brand", "country", "serving_size", "serving_unit",
+                "fiber", "carbs", "fat", "protein", "allergens", "traces",
+            ]
+        );
+    }
+
+    #[test]
+    fn nutrient_lists_accept_aliases_and_skip_duplicates() {
+        let names = column_names("energy_kcal, carbs, carbohydrates, salt");
+        assert_eq!(names[6..10], ["energy_kcal", "carbs", "salt", "allergens"]);
+    }
+
+    #[test]
+    fn unknown_nutrients_are_rejected() {
+        let nutrient_columns = parse_columns("energy_kcal,caffeine_pills").unwrap();
+        assert!(Schema::new(RowFormat::Array, &nutrient_columns).is_err());
+        assert!(parse_columns(" , ").is_err());
+    }
+
+    fn column_units(schema: &Schema) -> Vec<(String, Option<String>)> {
+        schema.columns.iter().map(|c| (c.name.to_string(), c.unit.as_ref().map(|u| u.to_string()))).collect()
+    }
+
+    #[test]
+    fn nutrient_columns_use_the_display_unit() {
+        let nutrient_columns = parse_columns("energy_kcal,vitamin_b12,alcohol,carbs").unwrap();
+        let schema = Schema::new(RowFormat::Array, &nutrient_columns).unwrap();
+        let unit = |name: &str| column_units(&schema).into_iter().find(|(n, _)| n == name).and_then(|(_, unit)| unit);
+        assert_eq!(unit("energy_kcal").as_deref(), Some("kcal/100g"));
+        assert_eq!(unit("vitamin_b12").as_deref(), Some("µg/100g"));
+        assert_eq!(unit("alcohol").as_deref(), Some("% vol"));
+        assert_eq!(unit("carbs").as_deref(), Some("g/100g"));
+
+        let mut breakdown = Breakdown::new(vec![None; nutrients::registry().defs().len()], Default::default());
+        breakdown.set("vitamin_b12", Some(0.000_002_5));
+        breakdown.set("carbohydrates", Some(12.5));
+        assert_eq!(schema.nutrient_values(&breakdown), [None, Some(2.5), None, Some(12.5)]);
+    }
+
+    #[test]
+    fn nutrient_columns_cannot_shadow_other_columns() {
+        let config = std::env::temp_dir().join(format!("catalog-nutrients-{}.json", std::process::id()));
+        fs::write(
+            &config,
+            r#"[
+                {"name": "allergens", "column": "allergens_100g", "group": "other", "unit": "g"},
+                {"name": "carbs", "column": "carbs_100g", "group": "macros", "unit": "g"}
+            ]"#,
+        )
+        .unwrap();
+        let registry = NutrientRegistry::with_config(&config).unwrap();
+        fs::remove_file(&config).unwrap();
+
+        let schema = |raw: &str| Schema::with_registry(RowFormat::Array, &parse_columns(raw).unwrap(), &registry);
+        assert!(schema("fat,allergens").is_err());
+        // `full` also has `carbohydrates`, published as `carbs` like the custom nutrient
+        assert!(schema("full").is_err());
+        assert!(schema("fat,carbohydrates").is_ok());
+        assert!(schema("label").is_ok());
+    }
+
+    /// Decompressed lines of a catalog file, header first.
+    fn read_lines(path: &Path) -> Vec<String> {
+        let reader = BufReader::new(brotli::Decompressor::new(File::open(path).unwrap(), 4096));
+        reader.lines().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn sorts_in_runs_and_replaces_a_previous_catalog() {
+        let catalog_dir = std::env::temp_dir().join(format!("catalog-sort-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&catalog_dir);
+        fs::create_dir_all(&catalog_dir).unwrap();
+        // Leftovers of a bigger catalog: an extra chunk and a sort run
+        fs::write(catalog_dir.join("catalog-0009.jsonl.br"), "stale").unwrap();
+        fs::write(catalog_dir.join("catalog.run-0007.tsv"), "stale").unwrap();
+
+        // (popularity_key, unique_scans_n, row) in CSV order
+        let spilled = [
+            ("", "", "a"),
+            ("5", "", "b"),
+            ("", "3", "c"),
+            ("9", "1", "d"),
+            ("5", "", "e"),
+            ("", "", "f"),
+            ("5", "2", "g"),
+        ];
+        let unsorted_path = catalog_dir.join("catalog.unsorted.tsv");
+        let contents: String = spilled.iter().map(|(key, scans, row)| format!("{}\t{}\t{}\n", key, scans, row)).collect();
+        fs::write(&unsorted_path, contents).unwrap();
+
+        let rows = write_sorted(&unsorted_path, &catalog_dir, "fr", 3, 2, 2).unwrap();
+        assert_eq!(rows, 7);
+
+        // Most popular first; ties keep CSV order even across sort runs
+        let expected = ["d", "g", "b", "e", "c", "a", "f"];
+        assert_eq!(read_lines(&catalog_dir.join("catalog.jsonl.br"))[1..], expected);
+        assert_eq!(read_lines(&catalog_dir.join("catalog-0001.jsonl.br"))[1..], expected[..3]);
+        assert_eq!(read_lines(&catalog_dir.join("catalog-0003.jsonl.br"))[1..], expected[6..]);
+        assert_eq!(read_lines(&catalog_dir.join("top.jsonl.br"))[1..], expected[..2]);
+        assert!(read_lines(&catalog_dir.join("top.jsonl.br"))[0].contains("schema_version"));
+
+        let mut names: Vec<String> = fs::read_dir(&catalog_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                "catalog-0001.jsonl.br", "catalog-0002.jsonl.br", "catalog-0003.jsonl.br",
+                "catalog.jsonl.br", "catalog.unsorted.tsv", "manifest.json", "top.jsonl.br",
+            ]
+        );
+
+        let manifest: serde_json::Value = serde_json::from_str(&fs::read_to_string(catalog_dir.join(MANIFEST_FILE)).unwrap()).unwrap();
+        let first_rows: Vec<u64> = manifest["chunks"].as_array().unwrap().iter().map(|c| c["first_row"].as_u64().unwrap()).collect();
+        assert_eq!(first_rows, [0, 3, 6]);
+        assert_eq!(manifest["rows"], 7);
+        fs::remove_dir_all(&catalog_dir).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::paged_index;
+
+struct Node {
+    name: String,
+    parents: Vec<String>,
+}
+
+/// The OFF category taxonomy, keyed by index key ("breakfast-cereals").
+#[derive(Default)]
+pub struct Taxonomy {
+    nodes: HashMap<String, Node>,
+    // "lang:key-of-any-synonym" -> index key
+    by_name: HashMap<String, String>,
+}
+
+impl Taxonomy {
+    /// Parses an OFF `categories.txt` file.
+    ///
+    /// Entries are blank-line separated blocks of `lang:name, synonym, ...`
+    /// lines, with `< lang:Parent name` lines naming their parents. The first
+    /// name of the first line identifies the entry.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read category taxonomy: {:?}", path))?;
+        Ok(Self::parse(&contents))
+    }
+
+    fn parse(contents: &str) -> Self {
+        let mut taxonomy = Taxonomy::default();
+        let mut raw_parents: Vec<(String, Vec<String>)> = Vec::new();
+
+        for block in contents.replace("\r\n", "\n").split("\n\n") {
+            let lines: Vec<&str> = block
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .collect();
+            let Some(first) = lines.first() else {
+                continue;
+            };
+            if first.starts_with("stopwords:") || first.starts_with("synonyms:") {
+                continue;
+            }
+
+            let mut key: Option<String> = None;
+            let mut english_name: Option<String> = None;
+            let mut first_name: Option<String> = None;
+            let mut parents = Vec::new();
+            let mut synonyms = Vec::new();
+
+            for line in lines {
+                if let Some(parent) = line.strip_prefix('<') {
+                    parents.push(parent.trim().to_string());
+                    continue;
+                }
+                // Property lines ("wikidata:en:Q123") aren't names
+                let Some((lang, names)) = line.split_once(':') else {
+                    continue;
+                };
+                if !is_language(lang) {
+                    continue;
+                }
+                let names: Vec<&str> = names.split(',').map(str::trim).filter(|n| !n.is_empty()).collect();
+                let Some(name) = names.first() else {
+                    continue;
+                };
+
+                key.get_or_insert_with(|| index_key(lang, name));
+                first_name.get_or_insert_with(|| name.to_string());
+                if lang == "en" {
+                    english_name.get_or_insert_with(|| name.to_string());
+                }
+                synonyms.extend(names.iter().map(|n| name_id(lang, n)));
+            }
+
+            let Some(key) = key else {
+                continue;
+            };
+            for synonym in synonyms {
+                taxonomy.by_name.entry(synonym).or_insert_with(|| key.clone());
+            }
+            taxonomy.nodes.insert(
+                key.clone(),
+                Node {
+                    name: english_name.or(first_name).unwrap_or_default(),
+                    parents: Vec::new(),
+                },
+            );
+            raw_parents.push((key, parents));
+        }
+
+        // Parents are referenced by name, so they resolve once every entry is known
+        for (key, parents) in raw_parents {
+            let resolved: Vec<String> = parents.iter().filter_map(|parent| taxonomy.lookup(parent)).collect();
+            if let Some(node) = taxonomy.nodes.get_mut(&key) {
+                node.parents = resolved;
+            }
+        }
+
+        taxonomy
+    }
+
+    pub fn category_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    fn lookup(&self, reference: &str) -> Option<String> {
+        let (lang, name) = reference.split_once(':').filter(|(lang, _)| is_language(lang))?;
+        self.by_name.get(&name_id(lang, name)).cloned()
+    }
+
+    /// Resolves a `main_category` value ("en:breakfast-cereals" or
+    /// "Breakfast cereals") to its key, falling back to the value's own key
+    /// when the taxonomy doesn't know it.
+    pub fn resolve(&self, raw: &str) -> Option<String> {
+        let raw = raw.trim();
+        let reference = if raw.split_once(':').is_some_and(|(lang, _)| is_language(lang)) {
+            raw.to_string()
+        } else {
+            format!("en:{}", raw)
+        };
+        self.lookup(&reference).or_else(|| {
+            let (lang, name) = reference.split_once(':')?;
+            let key = index_key(lang, name);
+            (!key.is_empty()).then_some(key)
+        })
+    }
+
+    /// The category followed by all of its ancestors, nearest first.
+    pub fn with_ancestors(&self, key: &str) -> Vec<String> {
+        let mut seen: HashSet<&str> = HashSet::new();
+        let mut ordered = vec![key.to_string()];
+        seen.insert(key);
+        let mut i = 0;
+        while i < ordered.len() {
+            if let Some(node) = self.nodes.get(&ordered[i]) {
+                for parent in &node.parents {
+                    if seen.insert(parent.as_str()) {
+                        ordered.push(parent.clone());
+                    }
+                }
+            }
+            i += 1;
+        }
+        ordered
+    }
+
+    pub fn name<'a>(&'a self, key: &'a str) -> &'a str {
+        self.nodes.get(key).map(|node| node.name.as_str()).unwrap_or(key)
+    }
+}
+
+fn is_language(prefix: &str) -> bool {
+    (2..=3).contains(&prefix.len()) && prefix.chars().all(|c| c.is_ascii_lowercase())
+}
+
+fn name_id(lang: &str, name: &str) -> String {
+    format!("{}:{}", lang, paged_index::key_for(name))
+}
+
+/// English entries are keyed by their name alone; others keep the language: "fr-galettes".
+fn index_key(lang: &str, name: &str) -> String {
+    let key = paged_index::key_for(name);
+    if lang == "en" || key.is_empty() {
+        key
+    } else {
+        format!("{}-{}", lang, key)
+    }
+}
+
+#[derive(Serialize)]
+struct TreeNode<'a> {
+    name: &'a str,
+    count: usize,
+    parents: Vec<&'a str>,
+    children: Vec<&'a str>,
+}
+
+#[derive(Serialize)]
+struct Tree<'a> {
+    roots: Vec<&'a str>,
+    categories: BTreeMap<&'a str, TreeNode<'a>>,
+}
+
+/// Product counts per category, for `_tree.json`.
+#[derive(Default)]
+pub struct CategoryTree {
+    counts: HashMap<String, usize>,
+}
+
+impl CategoryTree {
+    pub fn add(&mut self, keys: &[String]) {
+        for key in keys {
+            *self.counts.entry(key.clone()).or_insert(0) += 1;
+        }
+    }
+
+    /// Writes the hierarchy of every category with products, with per-category counts.
+    pub fn write(&self, path: &Path, taxonomy: &Taxonomy) -> Result<()> {
+        let mut categories: BTreeMap<&str, TreeNode> = self
+            .counts
+            .iter()
+            .map(|(key, count)| {
+                let parents = taxonomy
+                    .nodes
+                    .get(key)
+                    .map(|node| {
+                        node.parents
+                            .iter()
+                            .map(String::as_str)
+                            .filter(|parent| self.counts.contains_key(*parent))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                let node = TreeNode {
+                    name: taxonomy.name(key),
+                    count: *count,
+                    parents,
+                    children: Vec::new(),
+                };
+                (key.as_str(), node)
+            })
+            .collect();
+
+        let edges: Vec<(&str, &str)> = categories
+            .iter()
+            .flat_map(|(key, node)| node.parents.iter().map(move |parent| (*parent, *key)))
+            .collect();
+        for (parent, child) in edges {
+            if let Some(node) = categories.get_mut(parent) {
+                node.children.push(child);
+            }
+        }
+
+        let roots = categories
+            .iter()
+            .filter(|(_, node)| node.parents.is_empty())
+            .map(|(key, _)| *key)
+            .collect();
+
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create category tree: {:?}", path))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &Tree { roots, categories })
+            .with_context(|| format!("Failed to write category tree: {:?}", path))?;
+        writer.flush()
+            .with_context(|| format!("Failed to flush category tree: {:?}", path))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TAXONOMY: &str = "\
+stopwords:en: the, of
+
+# Roots
+en:Plant-based foods, plant based
+fr:Aliments d'origine végétale
+wikidata:en:Q123
+
+< en:Plant-based foods
+en:Cereals and potatoes
+
+< en:cereals and potatoes
+< en:Snacks
+en:Breakfast cereals, cereal flakes
+fr:Céréales pour petit-déjeuner
+
+< fr:Galettes bretonnes
+fr:Galettes de blé noir
+
+fr:Galettes bretonnes
+< fr:Galettes de blé noir
+
+< en:Loop b
+en:Loop a
+
+< en:Loop a
+en:Loop b
+";
+
+    #[test]
+    fn parses_entries_synonyms_and_languages() {
+        let taxonomy = Taxonomy::parse(TAXONOMY);
+        assert_eq!(taxonomy.category_count(), 7);
+        assert_eq!(taxonomy.name("breakfast-cereals"), "Breakfast cereals");
+        // Entries without an English name keep their language in the key
+        assert_eq!(taxonomy.name("fr-galettes-de-ble-noir"), "Galettes de blé noir");
+        assert_eq!(taxonomy.name("not-a-category"), "not-a-category");
+    }
+
+    #[test]
+    fn resolves_tags_names_and_synonyms() {
+        let taxonomy = Taxonomy::parse(TAXONOMY);
+        let resolve = |raw| taxonomy.resolve(raw);
+        assert_eq!(resolve("en:breakfast-cereals").as_deref(), Some("breakfast-cereals"));
+        assert_eq!(resolve("Cereal flakes").as_deref(), Some("breakfast-cereals"));
+        assert_eq!(resolve("fr:céréales pour petit déjeuner").as_deref(), Some("breakfast-cereals"));
+        assert_eq!(resolve(" en:plant based ").as_deref(), Some("plant-based-foods"));
+        // Unknown categories fall back to their own key
+        assert_eq!(resolve("de:Müsli").as_deref(), Some("de-musli"));
+        assert_eq!(resolve("Granola bars").as_deref(), Some("granola-bars"));
+        assert_eq!(resolve(" ... "), None);
+    }
+
+    #[test]
+    fn ancestors_come_nearest_first_and_skip_unknown_parents() {
+        let taxonomy = Taxonomy::parse(TAXONOMY);
+        // `en:Snacks` has no entry of its own, so it isn't a parent
+        assert_eq!(
+            taxonomy.with_ancestors("breakfast-cereals"),
+            ["breakfast-cereals", "cereals-and-potatoes", "plant-based-foods"]
+        );
+        assert_eq!(taxonomy.with_ancestors("granola-bars"), ["granola-bars"]);
+    }
+
+    #[test]
+    fn ancestor_cycles_terminate() {
+        let taxonomy = Taxonomy::parse(TAXONOMY);
+        assert_eq!(taxonomy.with_ancestors("loop-a"), ["loop-a", "loop-b"]);
+        assert_eq!(
+            taxonomy.with_ancestors("fr-galettes-bretonnes"),
+            ["fr-galettes-bretonnes", "fr-galettes-de-ble-noir"]
+        );
+    }
+}
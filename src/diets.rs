@@ -0,0 +1,157 @@
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::ensure_dir;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Diet {
+    Vegan,
+    Vegetarian,
+    GlutenFree,
+    LactoseFree,
+    PalmOilFree,
+}
+
+impl Diet {
+    pub const ALL: [Diet; 5] = [
+        Diet::Vegan,
+        Diet::Vegetarian,
+        Diet::GlutenFree,
+        Diet::LactoseFree,
+        Diet::PalmOilFree,
+    ];
+
+    /// File stem of the diet's index file.
+    pub fn slug(self) -> &'static str {
+        match self {
+            Diet::Vegan => "vegan",
+            Diet::Vegetarian => "vegetarian",
+            Diet::GlutenFree => "gluten-free",
+            Diet::LactoseFree => "lactose-free",
+            Diet::PalmOilFree => "palm-oil-free",
+        }
+    }
+
+    // Label tags that certify the diet
+    fn label_tags(self) -> &'static [&'static str] {
+        match self {
+            Diet::Vegan => &["vegan"],
+            // A vegan label implies vegetarian
+            Diet::Vegetarian => &["vegetarian", "vegan"],
+            Diet::GlutenFree => &["gluten-free", "no-gluten"],
+            Diet::LactoseFree => &["lactose-free", "no-lactose"],
+            Diet::PalmOilFree => &["palm-oil-free", "no-palm-oil"],
+        }
+    }
+
+    // `ingredients_analysis_tags` values that establish the diet; OFF doesn't
+    // analyse ingredients for gluten or lactose
+    fn analysis_tags(self) -> &'static [&'static str] {
+        match self {
+            Diet::Vegan => &["vegan"],
+            Diet::Vegetarian => &["vegetarian", "vegan"],
+            Diet::GlutenFree | Diet::LactoseFree => &[],
+            Diet::PalmOilFree => &["palm-oil-free"],
+        }
+    }
+}
+
+/// The diets a product qualifies for, from its normalized `labels_tags` and
+/// `ingredients_analysis_tags`; either source is enough.
+pub fn classify(labels: &[String], analysis: &[String]) -> Vec<Diet> {
+    let has_any = |tags: &[String], wanted: &[&str]| tags.iter().any(|tag| wanted.contains(&tag.as_str()));
+    Diet::ALL
+        .into_iter()
+        .filter(|diet| has_any(labels, diet.label_tags()) || has_any(analysis, diet.analysis_tags()))
+        .collect()
+}
+
+/// Per-country lists of product codes for each diet.
+#[derive(Default)]
+pub struct DietIndex {
+    countries: HashMap<String, BTreeMap<Diet, Vec<String>>>,
+}
+
+impl DietIndex {
+    /// Records a product; every country seen gets a file for every diet, even an empty one.
+    pub fn add(&mut self, country_code: &str, code: &str, diets: &[Diet]) {
+        let country = self.countries.entry(country_code.to_string()).or_insert_with(|| {
+            Diet::ALL.into_iter().map(|diet| (diet, Vec::new())).collect()
+        });
+        for diet in diets {
+            country.entry(*diet).or_default().push(code.to_string());
+        }
+    }
+
+    /// Writes `{base_dir}/{country}/{diet}.json`, each a sorted JSON array of codes.
+    pub fn write(self, base_dir: &Path) -> Result<usize> {
+        let file_counts: Vec<usize> = self
+            .countries
+            .into_par_iter()
+            .map(|(country_code, diets)| -> Result<usize> {
+                let country_dir = base_dir.join(&country_code);
+                ensure_dir(&country_dir)?;
+
+                let file_count = diets.len();
+                for (diet, mut codes) in diets {
+                    codes.sort_unstable();
+                    codes.dedup();
+
+                    let path = country_dir.join(format!("{}.json", diet.slug()));
+                    let file = File::create(&path)
+                        .with_context(|| format!("Failed to create diet index: {:?}", path))?;
+                    let mut writer = BufWriter::new(file);
+                    serde_json::to_writer(&mut writer, &codes)
+                        .with_context(|| format!("Failed to write diet index: {:?}", path))?;
+                    writer.flush()
+                        .with_context(|| format!("Failed to flush diet index: {:?}", path))?;
+                }
+                Ok(file_count)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(file_counts.iter().sum())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::allergens::parse_tags;
+
+    fn diets(labels: &str, analysis: &str) -> Vec<Diet> {
+        classify(&parse_tags(labels), &parse_tags(analysis))
+    }
+
+    #[test]
+    fn labels_certify_diets() {
+        assert_eq!(diets("en:vegan", ""), [Diet::Vegan, Diet::Vegetarian]);
+        assert_eq!(diets("en:no-gluten,en:lactose-free", ""), [Diet::GlutenFree, Diet::LactoseFree]);
+        assert_eq!(diets("en:organic,en:fair-trade", ""), []);
+    }
+
+    #[test]
+    fn ingredient_analysis_establishes_diets() {
+        assert_eq!(diets("", "en:palm-oil-free,en:vegetarian"), [Diet::Vegetarian, Diet::PalmOilFree]);
+        // Analysis never establishes gluten or lactose free
+        assert_eq!(diets("", "en:gluten-free,en:lactose-free"), []);
+    }
+
+    #[test]
+    fn negative_and_uncertain_analysis_tags_qualify_for_nothing() {
+        let analysis = "en:non-vegan,en:maybe-vegetarian,en:palm-oil,en:may-contain-palm-oil,\
+                        en:vegan-status-unknown,en:palm-oil-content-unknown";
+        assert_eq!(diets("", analysis), []);
+        // Labels are certified, so they still count when the analysis disagrees
+        assert_eq!(diets("en:vegetarian", "en:non-vegetarian"), [Diet::Vegetarian]);
+    }
+
+    #[test]
+    fn untranslated_labels_are_not_matched() {
+        assert_eq!(diets("fr:vegan,de:glutenfrei", ""), []);
+    }
+}
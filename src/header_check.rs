@@ -0,0 +1,164 @@
+use csv::StringRecord;
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// A missing expected column that looks like it was renamed to an unmapped one.
+#[derive(Debug, Serialize)]
+pub struct RenameCandidate {
+    pub expected: String,
+    pub found: String,
+    pub distance: usize,
+}
+
+/// Differences between the CSV header and the columns the processor knows about.
+#[derive(Debug, Serialize)]
+pub struct DriftReport {
+    pub missing: Vec<String>,
+    pub missing_required: Vec<String>,
+    pub renamed: Vec<RenameCandidate>,
+    pub unmapped: Vec<String>,
+}
+
+impl DriftReport {
+    pub fn new(headers: &StringRecord, expected: &[&str], required: &[&str]) -> Self {
+        let actual: HashSet<&str> = headers.iter().collect();
+        let expected_set: HashSet<&str> = expected.iter().copied().collect();
+
+        let missing: Vec<String> = expected
+            .iter()
+            .filter(|column| !actual.contains(*column))
+            .map(|column| column.to_string())
+            .collect();
+        let missing_required = required
+            .iter()
+            .filter(|column| !actual.contains(*column))
+            .map(|column| column.to_string())
+            .collect();
+        let unmapped: Vec<String> = headers
+            .iter()
+            .filter(|column| !expected_set.contains(column))
+            .map(str::to_string)
+            .collect();
+
+        let renamed = missing
+            .iter()
+            .filter_map(|expected| {
+                unmapped
+                    .iter()
+                    .map(|found| (found, rename_distance(expected, found)))
+                    .filter(|(_, distance)| *distance <= rename_threshold(expected))
+                    .min_by_key(|(_, distance)| *distance)
+                    .map(|(found, distance)| RenameCandidate {
+                        expected: expected.clone(),
+                        found: found.clone(),
+                        distance,
+                    })
+            })
+            .collect();
+
+        Self {
+            missing,
+            missing_required,
+            renamed,
+            unmapped,
+        }
+    }
+
+    pub fn print_summary(&self) {
+        println!(
+            "Header check: {} missing ({} required), {} possible renames, {} unmapped columns",
+            self.missing.len(),
+            self.missing_required.len(),
+            self.renamed.len(),
+            self.unmapped.len()
+        );
+        for column in &self.missing {
+            let required = if self.missing_required.contains(column) { " (required)" } else { "" };
+            println!("   Missing: {}{}", column, required);
+        }
+        for candidate in &self.renamed {
+            println!("   Possible rename: {} -> {}", candidate.expected, candidate.found);
+        }
+        // Only unmapped nutrient columns are interesting; OFF has many metadata columns we ignore
+        for column in self.unmapped.iter().filter(|c| c.ends_with("_100g")) {
+            println!("   Unmapped nutrient column: {}", column);
+        }
+    }
+}
+
+fn rename_threshold(expected: &str) -> usize {
+    (expected.len() / 4).max(2)
+}
+
+/// Edit distance that ignores the `-`/`_` spelling differences common in OFF renames.
+fn rename_distance(a: &str, b: &str) -> usize {
+    let normalize = |s: &str| s.replace('-', "_").to_lowercase();
+    levenshtein(&normalize(a), &normalize(b))
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b_chars: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b_chars.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b_chars.len() + 1];
+        for (j, b_char) in b_chars.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b_chars.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn levenshtein_counts_single_character_edits() {
+        assert_eq!(levenshtein("", ""), 0);
+        assert_eq!(levenshtein("kitten", "sitting"), 3);
+        assert_eq!(levenshtein("fat_100g", "fat_100g"), 0);
+        assert_eq!(levenshtein("", "abc"), 3);
+        assert_eq!(levenshtein("crème", "creme"), 1);
+    }
+
+    #[test]
+    fn rename_distance_ignores_case_and_dash_underscore_swaps() {
+        assert_eq!(rename_distance("energy-kcal_100g", "Energy_kcal_100g"), 0);
+        assert_eq!(rename_distance("sugars_100g", "sugar_100g"), 1);
+    }
+
+    #[test]
+    fn reports_missing_renamed_and_unmapped_columns() {
+        let headers = StringRecord::from(vec!["code", "product_name", "energy_kcal_100g", "fibre_100g", "created_t"]);
+        let expected = ["code", "product_name", "brands", "energy-kcal_100g", "fiber_100g"];
+        let report = DriftReport::new(&headers, &expected, &["code", "brands"]);
+
+        assert_eq!(report.missing, ["brands", "energy-kcal_100g", "fiber_100g"]);
+        assert_eq!(report.missing_required, ["brands"]);
+        assert_eq!(report.unmapped, ["energy_kcal_100g", "fibre_100g", "created_t"]);
+
+        let renamed: Vec<(&str, &str, usize)> = report
+            .renamed
+            .iter()
+            .map(|c| (c.expected.as_str(), c.found.as_str(), c.distance))
+            .collect();
+        // "brands" has no unmapped column within its threshold of 2
+        assert_eq!(renamed, [("energy-kcal_100g", "energy_kcal_100g", 0), ("fiber_100g", "fibre_100g", 2)]);
+    }
+
+    #[test]
+    fn picks_the_closest_candidate_within_the_threshold() {
+        let headers = StringRecord::from(vec!["salt_100", "salt_100g_x", "completely_new"]);
+        let report = DriftReport::new(&headers, &["salt_100g"], &[]);
+        assert_eq!(report.renamed.len(), 1);
+        assert_eq!((report.renamed[0].found.as_str(), report.renamed[0].distance), ("salt_100", 1));
+
+        // Short names still allow two edits, long names a quarter of their length
+        assert_eq!(rename_threshold("fat"), 2);
+        assert_eq!(rename_threshold("saturated-fat_100g"), 4);
+    }
+}
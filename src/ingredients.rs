@@ -0,0 +1,286 @@
+use regex::Regex;
+use serde::Serialize;
+use std::sync::OnceLock;
+
+/// One entry of a parsed ingredient list.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Ingredient {
+    pub text: String,
+    pub percent: Option<f64>,
+    /// Additive code such as "E322", from "lecithins (E322)" or a bare "E330"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub e_number: Option<String>,
+    /// Marked as an allergen on the label, e.g. `_milk_`
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub allergen: bool,
+    pub sub_ingredients: Vec<Ingredient>,
+}
+
+// ---- Cached Regex ----
+fn percent_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?:min\.?\s*|max\.?\s*)?(\d+(?:[.,]\d+)?)\s*%").unwrap())
+}
+
+fn e_number_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?i)^e\s?-?(\d{3,4}[a-z]?)$").unwrap())
+}
+
+fn allergen_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"_([^_]+)_").unwrap())
+}
+
+/// Splits an `ingredients_text` value into a nested ingredient list.
+pub fn parse(text: &str) -> Vec<Ingredient> {
+    let segments = split_top_level(text);
+    let mut ingredients = Vec::new();
+
+    for (i, segment) in segments.iter().enumerate() {
+        let lower = segment.to_lowercase();
+
+        // "Contains 2% or less of: salt, yeast" groups everything after it
+        if lower.starts_with("contains") && (lower.contains("or less") || lower.contains("less than")) {
+            let (label, first) = split_group_label(segment);
+            let rest = std::iter::once(first).chain(segments[i + 1..].iter().map(String::as_str));
+            ingredients.push(Ingredient {
+                text: clean_text(label),
+                percent: None,
+                e_number: None,
+                allergen: false,
+                sub_ingredients: rest.flat_map(parse).collect(),
+            });
+            break;
+        }
+
+        // "Contains: milk, soy." and "May contain nuts" are allergen statements, not ingredients
+        if lower.starts_with("contains") || lower.starts_with("may contain") {
+            break;
+        }
+
+        if let Some(ingredient) = parse_segment(segment) {
+            ingredients.push(ingredient);
+        }
+    }
+
+    ingredients
+}
+
+fn parse_segment(segment: &str) -> Option<Ingredient> {
+    // "emulsifier: lecithins (E322)" is a class followed by its members
+    if let Some(colon) = top_level_position(segment, ':') {
+        let (class, members) = (&segment[..colon], &segment[colon + 1..]);
+        if !class.trim().is_empty() {
+            let mut ingredient = parse_segment(class)?;
+            ingredient.sub_ingredients.extend(parse(members));
+            return Some(ingredient);
+        }
+    }
+
+    let (main, groups) = split_parentheses(segment);
+    let mut percent = None;
+    let mut e_number = None;
+    let mut sub_ingredients = Vec::new();
+
+    for group in groups {
+        let group = group.trim();
+        if let Some(value) = only_percent(group) {
+            percent = Some(value);
+        } else if let Some(code) = as_e_number(group) {
+            e_number = Some(code);
+        } else {
+            sub_ingredients.extend(parse(group));
+        }
+    }
+
+    let mut main = main;
+    if let Some(captures) = percent_regex().captures(&main) {
+        percent = percent.or_else(|| parse_percent(&captures[1]));
+        main = main.replacen(&captures[0], " ", 1);
+    }
+
+    let allergen = allergen_regex().is_match(&main);
+    let text = clean_text(&allergen_regex().replace_all(&main, "$1"));
+    if text.is_empty() {
+        return None;
+    }
+    e_number = e_number.or_else(|| as_e_number(&text));
+
+    Some(Ingredient {
+        text,
+        percent,
+        e_number,
+        allergen,
+        sub_ingredients,
+    })
+}
+
+/// Splits on `,` `;` and sentence-ending `.` outside parentheses, keeping decimal commas and points.
+fn split_top_level(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+
+    for (i, &c) in chars.iter().enumerate() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+
+        let between_digits = i > 0
+            && chars[i - 1].is_ascii_digit()
+            && chars.get(i + 1).is_some_and(|next| next.is_ascii_digit());
+        let separator = depth == 0
+            && !between_digits
+            && match c {
+                ',' | ';' => true,
+                '.' => chars.get(i + 1).is_none_or(|next| next.is_whitespace()),
+                _ => false,
+            };
+
+        if separator {
+            segments.push(std::mem::take(&mut current));
+        } else {
+            current.push(c);
+        }
+    }
+    segments.push(current);
+
+    segments
+        .into_iter()
+        .map(|segment| segment.trim().to_string())
+        .filter(|segment| !segment.is_empty())
+        .collect()
+}
+
+/// Separates the text outside parentheses from the contents of each top-level group.
+fn split_parentheses(segment: &str) -> (String, Vec<String>) {
+    let mut main = String::new();
+    let mut groups: Vec<String> = Vec::new();
+    let mut depth = 0usize;
+
+    for c in segment.chars() {
+        match c {
+            '(' | '[' | '{' => {
+                if depth == 0 {
+                    groups.push(String::new());
+                    main.push(' ');
+                } else {
+                    groups.last_mut().unwrap().push(c);
+                }
+                depth += 1;
+            }
+            ')' | ']' | '}' if depth > 0 => {
+                depth -= 1;
+                if depth > 0 {
+                    groups.last_mut().unwrap().push(c);
+                }
+            }
+            _ if depth > 0 => groups.last_mut().unwrap().push(c),
+            _ => main.push(c),
+        }
+    }
+
+    (main, groups)
+}
+
+fn top_level_position(segment: &str, target: char) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in segment.char_indices() {
+        match c {
+            '(' | '[' | '{' => depth += 1,
+            ')' | ']' | '}' => depth = depth.saturating_sub(1),
+            _ if c == target && depth == 0 => return Some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// "Contains 2% or less of: salt" -> ("Contains 2% or less of", "salt")
+fn split_group_label(segment: &str) -> (&str, &str) {
+    if let Some(colon) = top_level_position(segment, ':') {
+        return (&segment[..colon], &segment[colon + 1..]);
+    }
+    match segment.to_lowercase().find(" of ") {
+        // " of " is ASCII, so the byte offset is valid in the original too
+        Some(of) if segment.is_char_boundary(of + 4) => (&segment[..of + 3], &segment[of + 4..]),
+        _ => (segment, ""),
+    }
+}
+
+fn only_percent(group: &str) -> Option<f64> {
+    let captures = percent_regex().captures(group)?;
+    (captures[0].len() == group.len()).then(|| parse_percent(&captures[1])).flatten()
+}
+
+fn parse_percent(raw: &str) -> Option<f64> {
+    raw.replace(',', ".").parse().ok().filter(|p: &f64| (0.0..=100.0).contains(p))
+}
+
+fn as_e_number(text: &str) -> Option<String> {
+    let captures = e_number_regex().captures(text.trim())?;
+    Some(format!("E{}", captures[1].to_lowercase()))
+}
+
+fn clean_text(text: &str) -> String {
+    let collapsed = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    collapsed
+        .trim_matches(|c: char| c.is_whitespace() || "*.:-–".contains(c))
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(ingredients: &[Ingredient]) -> Vec<&str> {
+        ingredients.iter().map(|i| i.text.as_str()).collect()
+    }
+
+    #[test]
+    fn splits_top_level_list() {
+        let parsed = parse("Sugar, palm oil, hazelnuts 13%, skimmed _milk_ powder 8,7%, fat-reduced cocoa 7.4%.");
+        assert_eq!(texts(&parsed), ["Sugar", "palm oil", "hazelnuts", "skimmed milk powder", "fat-reduced cocoa"]);
+        assert_eq!(parsed[2].percent, Some(13.0));
+        assert_eq!(parsed[3].percent, Some(8.7));
+        assert!(parsed[3].allergen);
+        assert_eq!(parsed[4].percent, Some(7.4));
+    }
+
+    #[test]
+    fn nests_parentheses() {
+        let parsed = parse("milk chocolate 20% (sugar, cocoa butter, _milk_ (powder)), salt");
+        assert_eq!(texts(&parsed), ["milk chocolate", "salt"]);
+        assert_eq!(parsed[0].percent, Some(20.0));
+        assert_eq!(texts(&parsed[0].sub_ingredients), ["sugar", "cocoa butter", "milk"]);
+        assert_eq!(texts(&parsed[0].sub_ingredients[2].sub_ingredients), ["powder"]);
+    }
+
+    #[test]
+    fn reads_percent_and_e_number_groups() {
+        let parsed = parse("tomatoes (62 %), emulsifier: lecithins (E 322), E330");
+        assert_eq!(parsed[0].percent, Some(62.0));
+        assert!(parsed[0].sub_ingredients.is_empty());
+        assert_eq!(parsed[1].text, "emulsifier");
+        assert_eq!(parsed[1].sub_ingredients[0].text, "lecithins");
+        assert_eq!(parsed[1].sub_ingredients[0].e_number.as_deref(), Some("E322"));
+        assert_eq!(parsed[2].e_number.as_deref(), Some("E330"));
+    }
+
+    #[test]
+    fn groups_contains_or_less() {
+        let parsed = parse("Enriched flour, water, contains 2% or less of: salt, yeast. Contains: wheat.");
+        assert_eq!(texts(&parsed), ["Enriched flour", "water", "contains 2% or less of"]);
+        assert_eq!(texts(&parsed[2].sub_ingredients), ["salt", "yeast"]);
+    }
+
+    #[test]
+    fn stops_at_allergen_statement() {
+        let parsed = parse("oats, sugar. May contain nuts, sesame.");
+        assert_eq!(texts(&parsed), ["oats", "sugar"]);
+    }
+}
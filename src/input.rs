@@ -0,0 +1,386 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use csv::{Position, Reader, ReaderBuilder, StringRecord};
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+use xz2::read::XzDecoder;
+
+use crate::languages;
+use crate::nutrients;
+use crate::CSV_SEPARATOR;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum InputFormat {
+    /// Detect from the first byte of the decompressed input
+    Auto,
+    /// OFF tab-separated CSV export
+    Csv,
+    /// OFF JSONL dump, one product object per line
+    Jsonl,
+}
+
+/// Input compression, detected from the stream's magic bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+    Xz,
+    Plain,
+}
+
+impl Compression {
+    fn detect(magic: &[u8]) -> Self {
+        match magic {
+            [0x1f, 0x8b, ..] => Compression::Gzip,
+            [0x28, 0xb5, 0x2f, 0xfd, ..] => Compression::Zstd,
+            [b'B', b'Z', b'h', ..] => Compression::Bzip2,
+            [0xfd, b'7', b'z', b'X', b'Z', 0x00, ..] => Compression::Xz,
+            _ => Compression::Plain,
+        }
+    }
+}
+
+/// A row that couldn't be read at all.
+#[derive(Debug)]
+pub struct InputError {
+    pub row: Option<u64>,
+    pub message: String,
+}
+
+/// Raw product records sharing one header, in CSV column terms; `code` is
+/// always the first column.
+pub trait RecordSource {
+    fn format(&self) -> InputFormat;
+    fn headers(&self) -> &StringRecord;
+    /// The next record, or `None` at the end of the input.
+    fn next_record(&mut self) -> Option<Result<StringRecord, InputError>>;
+}
+
+/// Opens an input file, or stdin for `-`, as a record source of the given format.
+///
+/// Compression is detected from the magic bytes. JSONL products are mapped
+/// onto `text_columns`, the registry's nutrient columns and the localized
+/// name columns of `languages::NAME_LANGUAGES`.
+pub fn open(path: &Path, format: InputFormat, text_columns: &[&str]) -> Result<(Box<dyn RecordSource>, Compression)> {
+    let raw: Box<dyn Read> = if path == Path::new("-") {
+        Box::new(io::stdin().lock())
+    } else {
+        Box::new(File::open(path).with_context(|| format!("Failed to open input file: {:?}", path))?)
+    };
+    let (compression, decompressed) = decompress(raw).with_context(|| format!("Failed to read input file: {:?}", path))?;
+    let mut reader = BufReader::with_capacity(64 * 1024, decompressed);
+
+    let format = match format {
+        InputFormat::Auto => detect_format(&mut reader).with_context(|| format!("Failed to read input file: {:?}", path))?,
+        format => format,
+    };
+    let source: Box<dyn RecordSource> = match format {
+        InputFormat::Jsonl => Box::new(JsonlSource::new(reader, jsonl_columns(text_columns))),
+        _ => Box::new(CsvSource::new(reader)?),
+    };
+    Ok((source, compression))
+}
+
+// Longest magic number `Compression::detect` looks at (xz)
+const MAGIC_LEN: usize = 6;
+
+/// Wraps the stream in the decoder its magic bytes call for. Concatenated
+/// streams (`cat a.gz b.gz`) are read to the end.
+fn decompress(mut raw: Box<dyn Read>) -> Result<(Compression, Box<dyn Read>)> {
+    // A pipe may hand over fewer bytes per read than the magic number holds
+    let mut magic = Vec::with_capacity(MAGIC_LEN);
+    (&mut raw).take(MAGIC_LEN as u64).read_to_end(&mut magic)?;
+    let compression = Compression::detect(&magic);
+    let reader = BufReader::with_capacity(64 * 1024, io::Cursor::new(magic).chain(raw));
+    let decompressed: Box<dyn Read> = match compression {
+        Compression::Gzip => Box::new(MultiGzDecoder::new(reader)),
+        Compression::Zstd => Box::new(zstd::stream::read::Decoder::with_buffer(reader)?),
+        Compression::Bzip2 => Box::new(MultiBzDecoder::new(reader)),
+        Compression::Xz => Box::new(XzDecoder::new_multi_decoder(reader)),
+        Compression::Plain => Box::new(reader),
+    };
+    Ok((compression, decompressed))
+}
+
+/// JSONL when the first non-blank byte opens an object, CSV otherwise.
+/// Leading blanks are consumed, as a buffer may hold nothing but them.
+fn detect_format(reader: &mut impl BufRead) -> std::io::Result<InputFormat> {
+    loop {
+        let buffer = reader.fill_buf()?;
+        let Some(first) = buffer.iter().find(|b| !b.is_ascii_whitespace()) else {
+            if buffer.is_empty() {
+                return Ok(InputFormat::Csv);
+            }
+            let blank = buffer.len();
+            reader.consume(blank);
+            continue;
+        };
+        return Ok(if *first == b'{' { InputFormat::Jsonl } else { InputFormat::Csv });
+    }
+}
+
+pub struct CsvSource<R: Read> {
+    reader: Reader<R>,
+    headers: StringRecord,
+}
+
+impl<R: Read> CsvSource<R> {
+    pub fn new(reader: R) -> Result<Self> {
+        let mut reader = ReaderBuilder::new()
+            .delimiter(CSV_SEPARATOR)
+            .flexible(true)
+            .from_reader(reader);
+        let headers = reader.headers().with_context(|| "Failed to read CSV header")?.clone();
+        Ok(Self { reader, headers })
+    }
+}
+
+impl<R: Read> RecordSource for CsvSource<R> {
+    fn format(&self) -> InputFormat {
+        InputFormat::Csv
+    }
+
+    fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    fn next_record(&mut self) -> Option<Result<StringRecord, InputError>> {
+        let mut record = StringRecord::new();
+        match self.reader.read_record(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(e) => Some(Err(InputError {
+                row: e.position().map(|p| p.line()),
+                message: e.to_string(),
+            })),
+        }
+    }
+}
+
+fn jsonl_columns(text_columns: &[&str]) -> Vec<String> {
+    let mut columns: Vec<String> = text_columns.iter().map(|c| c.to_string()).collect();
+    columns.extend(nutrients::registry().defs().iter().map(|def| def.column.to_string()));
+    for base in ["product_name", "generic_name"] {
+        columns.extend(languages::NAME_LANGUAGES.iter().map(|lang| format!("{}_{}", base, lang)));
+    }
+    columns
+}
+
+pub struct JsonlSource<R: BufRead> {
+    lines: std::io::Lines<R>,
+    line: u64,
+    headers: StringRecord,
+}
+
+impl<R: BufRead> JsonlSource<R> {
+    pub fn new(reader: R, columns: Vec<String>) -> Self {
+        Self {
+            lines: reader.lines(),
+            line: 0,
+            headers: StringRecord::from(columns),
+        }
+    }
+
+    fn record(&self, line: &str) -> Result<StringRecord, String> {
+        let product = match serde_json::from_str::<Value>(line) {
+            Ok(Value::Object(product)) => product,
+            Ok(_) => return Err("expected a JSON object".to_string()),
+            Err(e) => return Err(e.to_string()),
+        };
+        let fields: Vec<String> = self
+            .headers
+            .iter()
+            .map(|column| column_value(&product, column).unwrap_or_default())
+            .collect();
+
+        let mut record = StringRecord::from(fields);
+        let mut position = Position::new();
+        position.set_line(self.line);
+        record.set_position(Some(position));
+        Ok(record)
+    }
+}
+
+impl<R: BufRead> RecordSource for JsonlSource<R> {
+    fn format(&self) -> InputFormat {
+        InputFormat::Jsonl
+    }
+
+    fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    fn next_record(&mut self) -> Option<Result<StringRecord, InputError>> {
+        loop {
+            let line = self.lines.next()?;
+            self.line += 1;
+            let result = line
+                .map_err(|e| e.to_string())
+                .and_then(|line| if line.trim().is_empty() { Ok(None) } else { self.record(&line).map(Some) });
+            match result {
+                Ok(Some(record)) => return Some(Ok(record)),
+                Ok(None) => continue,
+                Err(message) => return Some(Err(InputError { row: Some(self.line), message })),
+            }
+        }
+    }
+}
+
+/// A CSV column's value in a dump product. Nutrients live under `nutriments`
+/// with the CSV's `*_100g` keys; lists are joined with commas like the CSV's
+/// `*_tags` columns.
+fn column_value(product: &Map<String, Value>, column: &str) -> Option<String> {
+    if column.ends_with("_100g") {
+        return product.get("nutriments")?.get(column).and_then(text);
+    }
+    if column == "main_category" {
+        // `categories_tags` runs from the broadest category to the most specific,
+        // which is what the CSV export publishes as the main category
+        let most_specific = || product.get("categories_tags")?.as_array()?.last().and_then(text);
+        return product.get(column).and_then(text).or_else(most_specific);
+    }
+    // Fields the dump may only carry in another form
+    let fallback = match column {
+        "code" => "_id",
+        "allergens" => "allergens_tags",
+        "traces" => "traces_tags",
+        "countries" => "countries_tags",
+        _ => "",
+    };
+    product
+        .get(column)
+        .and_then(text)
+        .or_else(|| product.get(fallback).and_then(text))
+}
+
+fn text(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Array(items) => items.iter().filter_map(text).collect::<Vec<_>>().join(","),
+        _ => return None,
+    };
+    (!text.is_empty()).then_some(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jsonl_records(input: &str) -> (StringRecord, Vec<Result<StringRecord, InputError>>) {
+        let columns = ["code", "product_name", "countries", "allergens", "energy-kcal_100g"];
+        let mut source = JsonlSource::new(input.as_bytes(), columns.iter().map(|c| c.to_string()).collect());
+        let headers = source.headers().clone();
+        let records = std::iter::from_fn(|| source.next_record()).collect();
+        (headers, records)
+    }
+
+    #[test]
+    fn maps_dump_products_onto_csv_columns() {
+        let input = r#"{"_id": "0042", "product_name": "Tea", "countries_tags": ["en:france", "en:spain"], "allergens": "", "nutriments": {"energy-kcal_100g": 1.5}}"#;
+        let (_, records) = jsonl_records(input);
+        let record = records[0].as_ref().unwrap();
+        assert_eq!(record.iter().collect::<Vec<_>>(), ["0042", "Tea", "en:france,en:spain", "", "1.5"]);
+        assert_eq!(record.position().map(|p| p.line()), Some(1));
+    }
+
+    #[test]
+    fn main_category_falls_back_to_the_most_specific_category_tag() {
+        let columns = vec!["code".to_string(), "main_category".to_string()];
+        let input = concat!(
+            r#"{"code": "1", "categories_tags": ["en:snacks", "en:sweet-snacks", "en:biscuits"], "compared_to_category": "en:cookies"}"#,
+            "\n",
+            r#"{"code": "2", "main_category": "en:teas", "categories_tags": ["en:beverages", "en:hot-beverages"]}"#,
+            "\n",
+            r#"{"code": "3", "compared_to_category": "en:cookies"}"#,
+        );
+        let mut source = JsonlSource::new(input.as_bytes(), columns);
+        let main_categories: Vec<String> = std::iter::from_fn(|| source.next_record())
+            .map(|record| record.unwrap()[1].to_string())
+            .collect();
+        assert_eq!(main_categories, ["en:biscuits", "en:teas", ""]);
+    }
+
+    #[test]
+    fn reports_malformed_lines_and_skips_blank_ones() {
+        let (_, records) = jsonl_records("\n[1, 2]\n{\"code\": \"1\"}\n{oops\n");
+        let rows: Vec<Option<u64>> = records
+            .iter()
+            .map(|r| match r {
+                Ok(record) => record.position().map(|p| p.line()),
+                Err(e) => e.row,
+            })
+            .collect();
+        assert_eq!(rows, [Some(2), Some(3), Some(4)]);
+        assert!(records[0].is_err() && records[1].is_ok() && records[2].is_err());
+    }
+
+    #[test]
+    fn detects_compression_from_magic_bytes() {
+        let cases: [(&[u8], Compression); 6] = [
+            (&[0x1f, 0x8b, 0x08, 0x00], Compression::Gzip),
+            (&[0x28, 0xb5, 0x2f, 0xfd, 0x04], Compression::Zstd),
+            (b"BZh91AY&SY", Compression::Bzip2),
+            (&[0xfd, b'7', b'z', b'X', b'Z', 0x00, 0x00], Compression::Xz),
+            (b"code\tproduct_name", Compression::Plain),
+            (b"", Compression::Plain),
+        ];
+        for (magic, expected) in cases {
+            assert_eq!(Compression::detect(magic), expected, "{:?}", magic);
+        }
+    }
+
+    /// A pipe that hands over one byte per read.
+    struct Trickle(io::Cursor<Vec<u8>>);
+
+    impl Trickle {
+        fn new(bytes: &[u8]) -> Self {
+            Self(io::Cursor::new(bytes.to_vec()))
+        }
+    }
+
+    impl Read for Trickle {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn detects_format_from_first_byte() {
+        assert_eq!(detect_format(&mut "\n  {\"code\": 1}".as_bytes()).unwrap(), InputFormat::Jsonl);
+        assert_eq!(detect_format(&mut "code\tproduct_name\n".as_bytes()).unwrap(), InputFormat::Csv);
+        assert_eq!(detect_format(&mut "".as_bytes()).unwrap(), InputFormat::Csv);
+        let mut trickle = BufReader::new(Trickle::new(b" \n\t{\"code\": 1}"));
+        assert_eq!(detect_format(&mut trickle).unwrap(), InputFormat::Jsonl);
+    }
+
+    #[test]
+    fn detects_compression_from_a_trickling_pipe() {
+        let mut gzip = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        io::Write::write_all(&mut gzip, b"code\n42\n").unwrap();
+        let gzip = gzip.finish().unwrap();
+        let mut xz = Vec::new();
+        xz2::read::XzEncoder::new(&b"code\n42\n"[..], 6).read_to_end(&mut xz).unwrap();
+
+        for (compressed, expected) in [(&gzip, Compression::Gzip), (&xz, Compression::Xz)] {
+            let (compression, mut decompressed) = decompress(Box::new(Trickle::new(compressed))).unwrap();
+            assert_eq!(compression, expected);
+            let mut text = String::new();
+            decompressed.read_to_string(&mut text).unwrap();
+            assert_eq!(text, "code\n42\n");
+        }
+
+        // Plain input shorter than any magic number is passed through whole
+        let (compression, mut decompressed) = decompress(Box::new(Trickle::new(b"ab"))).unwrap();
+        assert_eq!(compression, Compression::Plain);
+        let mut text = String::new();
+        decompressed.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "ab");
+    }
+}
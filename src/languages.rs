@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+
+/// Every language `country_languages` can return, for inputs whose localized
+/// fields have to be enumerated up front.
+pub const NAME_LANGUAGES: &[&str] = &[
+    "ar", "bg", "bs", "cs", "da", "de", "el", "en", "es", "et", "fa", "fi", "fr", "he", "hi", "hr", "hu", "id",
+    "is", "it", "ja", "ko", "lt", "lv", "mk", "ms", "nb", "nl", "no", "pl", "pt", "ro", "ru", "sk", "sl", "sq",
+    "sr", "sv", "th", "tr", "uk", "vi", "zh",
+];
+
+/// Languages a country's catalog prefers, most preferred first.
+///
+/// Countries with several official languages list the one most OFF
+/// contributors there write in first.
+pub fn country_languages(country_code: &str) -> &'static [&'static str] {
+    match country_code {
+        "fr" | "mc" | "lu" | "pf" | "nc" | "re" | "gp" | "mq" | "yt" | "gf" | "pm" | "sn" | "ci" | "cm" | "ma"
+        | "tn" | "dz" | "mg" | "ml" | "bf" | "ne" | "td" | "ga" | "cd" | "cg" | "bj" | "tg" | "ht" => &["fr"],
+        "be" => &["fr", "nl", "de"],
+        "ch" => &["de", "fr", "it"],
+        "ca" => &["en", "fr"],
+        "de" | "at" | "li" => &["de"],
+        "es" | "mx" | "ar" | "co" | "cl" | "pe" | "ve" | "ec" | "gt" | "cu" | "bo" | "do" | "hn" | "py" | "sv"
+        | "ni" | "cr" | "pa" | "uy" => &["es"],
+        "it" | "sm" | "va" => &["it"],
+        "pt" | "br" | "ao" | "mz" => &["pt"],
+        "nl" | "sr" => &["nl"],
+        "us" | "gb" | "ie" | "au" | "nz" | "za" | "ng" | "gh" | "ke" | "jm" | "sg" | "ph" => &["en"],
+        "in" => &["en", "hi"],
+        "dk" => &["da"],
+        "se" => &["sv"],
+        "no" => &["nb", "no"],
+        "fi" => &["fi", "sv"],
+        "is" => &["is"],
+        "pl" => &["pl"],
+        "cz" => &["cs"],
+        "sk" => &["sk"],
+        "hu" => &["hu"],
+        "ro" | "md" => &["ro"],
+        "bg" => &["bg"],
+        "gr" | "cy" => &["el"],
+        "hr" => &["hr"],
+        "si" => &["sl"],
+        "rs" => &["sr"],
+        "ba" => &["bs", "hr", "sr"],
+        "mk" => &["mk"],
+        "al" => &["sq"],
+        "lt" => &["lt"],
+        "lv" => &["lv"],
+        "ee" => &["et"],
+        "ru" | "by" | "kz" => &["ru"],
+        "ua" => &["uk"],
+        "tr" => &["tr"],
+        "il" => &["he"],
+        "sa" | "ae" | "eg" | "jo" | "lb" | "kw" | "qa" | "bh" | "om" | "iq" | "sy" | "ye" | "ly" => &["ar"],
+        "ir" => &["fa"],
+        "cn" | "tw" | "hk" => &["zh"],
+        "jp" => &["ja"],
+        "kr" => &["ko"],
+        "th" => &["th"],
+        "vn" => &["vi"],
+        "id" => &["id"],
+        "my" => &["ms", "en"],
+        _ => &[],
+    }
+}
+
+/// The language suffix of a localized column such as `product_name_fr`.
+pub fn localized_column<'a>(header: &'a str, base: &str) -> Option<&'a str> {
+    let lang = header.strip_prefix(base)?.strip_prefix('_')?;
+    let valid = (2..=3).contains(&lang.len()) && lang.chars().all(|c| c.is_ascii_lowercase());
+    valid.then_some(lang)
+}
+
+/// Normalizes a `lang` value ("FR", " fr ") to the key used in name maps.
+pub fn normalize(lang: &str) -> Option<String> {
+    let lang = lang.trim().to_lowercase();
+    (!lang.is_empty()).then_some(lang)
+}
+
+/// The name to show in a country's catalog: the first of its languages that
+/// has a localized name, otherwise the product's main name, otherwise the
+/// localized name of the alphabetically first language.
+pub fn best_name(names: &BTreeMap<String, String>, country_code: &str, main_name: Option<&str>) -> Option<String> {
+    country_languages(country_code)
+        .iter()
+        .find_map(|lang| names.get(*lang))
+        .map(String::as_str)
+        .or(main_name)
+        .or_else(|| names.values().next().map(String::as_str))
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_country_language_then_main_name_then_any_language() {
+        let names: BTreeMap<String, String> = [("de", "Schokolade"), ("fr", "Chocolat"), ("it", "Cioccolato")]
+            .into_iter()
+            .map(|(lang, name)| (lang.to_string(), name.to_string()))
+            .collect();
+        let only_italian: BTreeMap<String, String> = BTreeMap::from([("it".to_string(), "Cioccolato".to_string())]);
+        let cases = [
+            (&names, "fr", Some("Chocolate"), Some("Chocolat")),
+            // Switzerland prefers German, then French
+            (&names, "ch", Some("Chocolate"), Some("Schokolade")),
+            (&only_italian, "ch", Some("Chocolate"), Some("Cioccolato")),
+            (&names, "us", Some("Chocolate"), Some("Chocolate")),
+            (&names, "world", Some("Chocolate"), Some("Chocolate")),
+            (&names, "us", None, Some("Schokolade")),
+            (&BTreeMap::new(), "fr", None, None),
+        ];
+        for (names, country, main_name, expected) in cases {
+            assert_eq!(best_name(names, country, main_name).as_deref(), expected, "{} {:?}", country, main_name);
+        }
+    }
+
+    #[test]
+    fn reads_language_suffixes_of_localized_columns() {
+        let cases = [
+            ("product_name_fr", Some("fr")),
+            ("product_name_zh", Some("zh")),
+            ("product_name_nds", Some("nds")),
+            ("product_name", None),
+            ("product_name_", None),
+            ("product_name_FR", None),
+            ("product_name_fr_ca", None),
+            ("product_name_en-gb", None),
+            ("product_names_fr", None),
+            ("product_name_quantity", None),
+            ("generic_name_fr", None),
+        ];
+        for (header, expected) in cases {
+            assert_eq!(localized_column(header, "product_name"), expected, "{}", header);
+        }
+    }
+}
@@ -1,217 +1,231 @@
 use anyhow::{Context, Result};
-use csv::{ReaderBuilder, StringRecord};
-use flate2::read::GzDecoder;
-use brotli::enc::BrotliEncoderParams;
-use brotli::CompressorWriter;
+use csv::StringRecord;
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
-use regex::Regex;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use clap::Parser;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::{self, File};
-use std::io::{BufReader, BufWriter, Read, Write};
-use std::path::Path;
-use std::sync::OnceLock;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
 use std::time::Instant;
 use iso3166::{Country, LIST};
 
+mod allergens;
+mod autocomplete;
+mod brands;
+mod catalog;
+mod categories;
+mod diets;
+mod header_check;
+mod ingredients;
+mod input;
+mod languages;
+mod nutrients;
+mod paged_index;
+mod parquet_export;
+mod quality;
+mod rejects;
+mod serving;
+mod sqlite;
+mod text;
+
+use autocomplete::AutocompleteIndex;
+use brands::Brand;
+use catalog::{CatalogEntry, NutrientColumns, Popularity, RowFormat, Schema};
+use categories::{CategoryTree, Taxonomy};
+use diets::{Diet, DietIndex};
+use header_check::DriftReport;
+use ingredients::Ingredient;
+use input::InputFormat;
+use nutrients::{Breakdown, NutrientRegistry};
+use paged_index::PagedIndex;
+use parquet_export::ParquetExport;
+use quality::{Policy, QualityCheck, QualityIssue, QualityPolicy, QualitySummary};
+use rejects::{RejectLog, RejectReason, Rejection};
+use sqlite::SqliteExport;
+
 // ---- Config ----
 const INPUT_FILE: &str = "food_facts_raw_data/products.csv.gz";
+const CATEGORIES_TAXONOMY_FILE: &str = "food_facts_raw_data/categories.txt";
 const PRODUCTS_DIR: &str = "output/static/products";
 const CATALOG_BASE_DIR: &str = "output/static/indexes/catalogs";
+const AUTOCOMPLETE_BASE_DIR: &str = "output/static/indexes/autocomplete";
+const DIETS_BASE_DIR: &str = "output/static/indexes/diets";
+const BRANDS_BASE_DIR: &str = "output/static/indexes/brands";
+const CATEGORY_BASE_DIR: &str = "output/static/indexes/category";
+const NUTRIENTS_FILE: &str = "output/static/nutrients.json";
+const CATALOG_HEADER_FILE: &str = "output/static/indexes/catalog_header.json";
+const REJECTS_FILE: &str = "output/rejects.jsonl";
+// Catalog rows prefixed with their popularity, sorted into chunks at the end
+const UNSORTED_CATALOG_FILE: &str = "catalog.unsorted.tsv";
 
 const CSV_SEPARATOR: u8 = b'\t';
 const BATCH_SIZE: usize = 10_000;
+const AUTOCOMPLETE_TOP_N: usize = 50;
+const CATALOG_TOP_N: usize = 1_000;
+const CATALOG_CHUNK_ROWS: usize = 10_000;
+// Catalog rows sorted in memory at once, per country being finalized
+const CATALOG_SORT_RUN_ROWS: usize = 200_000;
+const PAGE_SIZE: usize = 500;
+
+// ---- CLI ----
+#[derive(Parser, Debug)]
+#[command(about = "Process the Open Food Facts CSV export into static files")]
+struct Args {
+    /// OFF CSV export or JSONL dump, plain or gzip, zstd, bzip2 or xz compressed; `-` reads stdin
+    #[arg(long, default_value = INPUT_FILE)]
+    input: PathBuf,
+
+    /// Input format; `auto` tells the JSONL dump from the CSV export by its first byte
+    #[arg(long, value_enum, default_value_t = InputFormat::Auto)]
+    input_format: InputFormat,
+
+    /// JSON file with extra or overriding nutrient definitions
+    #[arg(long)]
+    nutrients_config: Option<PathBuf>,
+
+    /// Keep unrecognised `*_100g` columns under `breakdown.extra`
+    #[arg(long)]
+    passthrough_unknown_nutrients: bool,
+
+    /// Abort if any required CSV column is missing; CSV input only
+    #[arg(long)]
+    strict: bool,
+
+    /// Write the CSV header drift report as JSON to this file; CSV input only
+    #[arg(long)]
+    drift_report: Option<PathBuf>,
+
+    /// Policy for data quality checks without an override
+    #[arg(long, value_enum, default_value_t = Policy::Flag)]
+    quality_default: Policy,
+
+    /// Per-check policy override, e.g. `sugars_exceed_carbs=repair` (repeatable)
+    #[arg(long, value_name = "CHECK=POLICY", value_parser = quality::parse_policy_override)]
+    quality_policy: Vec<(QualityCheck, Policy)>,
+
+    /// OFF `categories.txt` taxonomy; defaults to `food_facts_raw_data/categories.txt` when present
+    #[arg(long)]
+    categories_taxonomy: Option<PathBuf>,
+
+    /// Write catalog rows as positional arrays or as objects keyed by column name
+    #[arg(long, value_enum, default_value_t = RowFormat::Array)]
+    catalog_rows: RowFormat,
+
+    /// Nutrient columns of the catalogs: `macros`, `label`, `full`, or a
+    /// comma-separated list of nutrient names, e.g. `energy_kcal,sugars,salt`
+    #[arg(long, value_name = "PRESET|NUTRIENTS", value_parser = catalog::parse_columns, default_value = "macros")]
+    catalog_columns: NutrientColumns,
+
+    /// Also write every product into a normalized SQLite database at this path
+    #[arg(long)]
+    sqlite: Option<PathBuf>,
+
+    /// Write a SQLite subset with this country's products next to `--sqlite`, e.g. `fr`, `global` or `unknown` (repeatable)
+    #[arg(long, value_name = "COUNTRY", requires = "sqlite", value_parser = sqlite::parse_country)]
+    sqlite_country: Vec<String>,
+
+    /// Also write a Parquet dataset partitioned by country into this directory
+    #[arg(long, value_name = "DIR")]
+    parquet: Option<PathBuf>,
+}
 
 // ---- Data Structures ----
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize)]
 struct Product {
     code: String,
     product_name: Option<String>,
     generic_name: Option<String>,
     ingredients_text: Option<String>,
+    /// `ingredients_text` split into a nested list
+    ingredients: Vec<Ingredient>,
     brands: Option<String>,
     main_category: Option<String>,
+    /// Normalized `allergens` tags, e.g. "milk"
+    allergens: Vec<String>,
+    traces: Vec<String>,
+    /// Normalized `additives_tags`, e.g. "e322"
+    additives: Vec<String>,
+    additives_n: Option<u32>,
+    /// Language of `product_name` and `generic_name`
+    lang: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    names: BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    generic_names: BTreeMap<String, String>,
     serving_size: Option<f64>,
     serving_unit: Option<String>,
+    /// Household measure from the label, e.g. "1 cup"
+    serving_description: Option<String>,
+    serving_count: Option<f64>,
     breakdown: Breakdown,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    quality_issues: Vec<QualityIssue>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct Breakdown {
-    macros: MacroNutrients,
-    vitamins: Vitamins,
-    minerals: Minerals,
-    fats: FatBreakdown,
-    other: OtherNutrients,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct MacroNutrients {
-    energy_kcal: Option<f64>,
-    energy_kj: Option<f64>,
-    carbohydrates: Option<f64>,
-    fat: Option<f64>,
-    proteins: Option<f64>,
-    sugars: Option<f64>,
-    fiber: Option<f64>,
-    salt: Option<f64>,
-    added_sugars: Option<f64>,
-    sucrose: Option<f64>,
-    glucose: Option<f64>,
-    fructose: Option<f64>,
-    galactose: Option<f64>,
-    lactose: Option<f64>,
-    maltose: Option<f64>,
-    maltodextrins: Option<f64>,
-    psicose: Option<f64>,
-    starch: Option<f64>,
-    polyols: Option<f64>,
-    erythritol: Option<f64>,
-    isomalt: Option<f64>,
-    maltitol: Option<f64>,
-    sorbitol: Option<f64>,
-    soluble_fiber: Option<f64>,
-    insoluble_fiber: Option<f64>,
-    polydextrose: Option<f64>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Vitamins {
-    vitamin_a: Option<f64>,
-    beta_carotene: Option<f64>,
-    vitamin_d: Option<f64>,
-    vitamin_e: Option<f64>,
-    vitamin_k: Option<f64>,
-    vitamin_c: Option<f64>,
-    vitamin_b1: Option<f64>,
-    vitamin_b2: Option<f64>,
-    vitamin_pp: Option<f64>,
-    vitamin_b6: Option<f64>,
-    vitamin_b9: Option<f64>,
-    folates: Option<f64>,
-    vitamin_b12: Option<f64>,
-    biotin: Option<f64>,
-    pantothenic_acid: Option<f64>,
-    choline: Option<f64>,
-    phylloquinone: Option<f64>,
-    inositol: Option<f64>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct Minerals {
-    sodium: Option<f64>,
-    calcium: Option<f64>,
-    phosphorus: Option<f64>,
-    iron: Option<f64>,
-    magnesium: Option<f64>,
-    zinc: Option<f64>,
-    copper: Option<f64>,
-    manganese: Option<f64>,
-    fluoride: Option<f64>,
-    selenium: Option<f64>,
-    chromium: Option<f64>,
-    molybdenum: Option<f64>,
-    iodine: Option<f64>,
-    potassium: Option<f64>,
-    chloride: Option<f64>,
-    silica: Option<f64>,
-    bicarbonate: Option<f64>,
-    sulphate: Option<f64>,
-    nitrate: Option<f64>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct FatBreakdown {
-    saturated: Option<f64>,
-    unsaturated: Option<f64>,
-    monounsaturated: Option<f64>,
-    polyunsaturated: Option<f64>,
-    trans: Option<f64>,
-    cholesterol: Option<f64>,
-    omega_3: Option<f64>,
-    omega_6: Option<f64>,
-    omega_9: Option<f64>,
-    alpha_linolenic_acid: Option<f64>,
-    eicosapentaenoic_acid: Option<f64>,
-    docosahexaenoic_acid: Option<f64>,
-    linoleic_acid: Option<f64>,
-    arachidonic_acid: Option<f64>,
-    gamma_linolenic_acid: Option<f64>,
-    dihomo_gamma_linolenic_acid: Option<f64>,
-    oleic_acid: Option<f64>,
-    elaidic_acid: Option<f64>,
-    gondoic_acid: Option<f64>,
-    mead_acid: Option<f64>,
-    erucic_acid: Option<f64>,
-    nervonic_acid: Option<f64>,
-    butyric_acid: Option<f64>,
-    caproic_acid: Option<f64>,
-    caprylic_acid: Option<f64>,
-    capric_acid: Option<f64>,
-    lauric_acid: Option<f64>,
-    myristic_acid: Option<f64>,
-    palmitic_acid: Option<f64>,
-    stearic_acid: Option<f64>,
-    arachidic_acid: Option<f64>,
-    behenic_acid: Option<f64>,
-    lignoceric_acid: Option<f64>,
-    cerotic_acid: Option<f64>,
-    montanic_acid: Option<f64>,
-    melissic_acid: Option<f64>,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-struct OtherNutrients {
-    caffeine: Option<f64>,
-    taurine: Option<f64>,
-    carnitine: Option<f64>,
-    beta_glucan: Option<f64>,
-    alcohol: Option<f64>,
-    nucleotides: Option<f64>,
-    casein: Option<f64>,
-    serum_proteins: Option<f64>,
-    methylsulfonylmethane: Option<f64>,
-    energy_from_fat: Option<f64>,
-    added_salt: Option<f64>,
-}
-
-#[derive(Debug)]
-struct CatalogEntry {
-    code: String,
-    name: Option<String>,
-    brand: Option<String>,
-    country: Option<String>,
-    serving_size: Option<f64>,
-    serving_unit: Option<String>,
-    fiber: Option<f64>,
-    carbs: Option<f64>,
-    fat: Option<f64>,
-    protein: Option<f64>,
-}
-
-impl serde::Serialize for CatalogEntry {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        use serde::ser::SerializeSeq;
-        let mut seq = serializer.serialize_seq(Some(10))?;
-        seq.serialize_element(&self.code)?;
-        seq.serialize_element(&self.name)?;
-        seq.serialize_element(&self.brand)?;
-        seq.serialize_element(&self.country)?;
-        seq.serialize_element(&self.serving_size)?;
-        seq.serialize_element(&self.serving_unit)?;
-        seq.serialize_element(&self.fiber)?;
-        seq.serialize_element(&self.carbs)?;
-        seq.serialize_element(&self.fat)?;
-        seq.serialize_element(&self.protein)?;
-        seq.end()
+#[cfg(test)]
+impl Product {
+    /// A product with just a code and a name, for the export tests.
+    fn named(code: &str, name: &str) -> Self {
+        Self {
+            code: code.to_string(),
+            product_name: Some(name.to_string()),
+            generic_name: None,
+            ingredients_text: None,
+            ingredients: Vec::new(),
+            brands: None,
+            main_category: None,
+            allergens: Vec::new(),
+            traces: Vec::new(),
+            additives: Vec::new(),
+            additives_n: None,
+            lang: None,
+            names: BTreeMap::new(),
+            generic_names: BTreeMap::new(),
+            serving_size: None,
+            serving_unit: None,
+            serving_description: None,
+            serving_count: None,
+            breakdown: Breakdown::new(vec![None; nutrients::registry().defs().len()], BTreeMap::new()),
+            quality_issues: Vec::new(),
+        }
     }
 }
 
 // ---- Column Index ----
+// Non-nutrient columns read by the processor; `code` is always the first column
+const TEXT_COLUMNS: &[&str] = &[
+    "code",
+    "product_name",
+    "generic_name",
+    "ingredients_text",
+    "brands",
+    "main_category",
+    "countries",
+    "serving_size",
+    "serving_quantity",
+    "lang",
+    "allergens",
+    "traces",
+    "additives_tags",
+    "additives_n",
+    "labels_tags",
+    "ingredients_analysis_tags",
+    "unique_scans_n",
+    "popularity_key",
+];
+
+// Columns without which records can't pass validation
+const REQUIRED_COLUMNS: &[&str] = &[
+    "code",
+    "countries",
+    "energy-kcal_100g",
+    "carbohydrates_100g",
+    "fat_100g",
+    "proteins_100g",
+];
+
 struct ColumnIndex {
     product_name: Option<usize>,
     generic_name: Option<usize>,
@@ -221,125 +235,26 @@ struct ColumnIndex {
     countries: Option<usize>,
     serving_size: Option<usize>,
     serving_quantity: Option<usize>,
-    // Macros
-    energy_kcal_100g: Option<usize>,
-    energy_kj_100g: Option<usize>,
-    carbohydrates_100g: Option<usize>,
-    fat_100g: Option<usize>,
-    proteins_100g: Option<usize>,
-    sugars_100g: Option<usize>,
-    fiber_100g: Option<usize>,
-    salt_100g: Option<usize>,
-    added_sugars_100g: Option<usize>,
-    sucrose_100g: Option<usize>,
-    glucose_100g: Option<usize>,
-    fructose_100g: Option<usize>,
-    galactose_100g: Option<usize>,
-    lactose_100g: Option<usize>,
-    maltose_100g: Option<usize>,
-    maltodextrins_100g: Option<usize>,
-    psicose_100g: Option<usize>,
-    starch_100g: Option<usize>,
-    polyols_100g: Option<usize>,
-    erythritol_100g: Option<usize>,
-    isomalt_100g: Option<usize>,
-    maltitol_100g: Option<usize>,
-    sorbitol_100g: Option<usize>,
-    soluble_fiber_100g: Option<usize>,
-    insoluble_fiber_100g: Option<usize>,
-    polydextrose_100g: Option<usize>,
-    // Vitamins
-    vitamin_a_100g: Option<usize>,
-    beta_carotene_100g: Option<usize>,
-    vitamin_d_100g: Option<usize>,
-    vitamin_e_100g: Option<usize>,
-    vitamin_k_100g: Option<usize>,
-    vitamin_c_100g: Option<usize>,
-    vitamin_b1_100g: Option<usize>,
-    vitamin_b2_100g: Option<usize>,
-    vitamin_pp_100g: Option<usize>,
-    vitamin_b6_100g: Option<usize>,
-    vitamin_b9_100g: Option<usize>,
-    folates_100g: Option<usize>,
-    vitamin_b12_100g: Option<usize>,
-    biotin_100g: Option<usize>,
-    pantothenic_acid_100g: Option<usize>,
-    choline_100g: Option<usize>,
-    phylloquinone_100g: Option<usize>,
-    inositol_100g: Option<usize>,
-    // Minerals
-    sodium_100g: Option<usize>,
-    calcium_100g: Option<usize>,
-    phosphorus_100g: Option<usize>,
-    iron_100g: Option<usize>,
-    magnesium_100g: Option<usize>,
-    zinc_100g: Option<usize>,
-    copper_100g: Option<usize>,
-    manganese_100g: Option<usize>,
-    fluoride_100g: Option<usize>,
-    selenium_100g: Option<usize>,
-    chromium_100g: Option<usize>,
-    molybdenum_100g: Option<usize>,
-    iodine_100g: Option<usize>,
-    potassium_100g: Option<usize>,
-    chloride_100g: Option<usize>,
-    silica_100g: Option<usize>,
-    bicarbonate_100g: Option<usize>,
-    sulphate_100g: Option<usize>,
-    nitrate_100g: Option<usize>,
-    // Fats
-    saturated_fat_100g: Option<usize>,
-    unsaturated_fat_100g: Option<usize>,
-    monounsaturated_fat_100g: Option<usize>,
-    polyunsaturated_fat_100g: Option<usize>,
-    trans_fat_100g: Option<usize>,
-    cholesterol_100g: Option<usize>,
-    omega_3_fat_100g: Option<usize>,
-    omega_6_fat_100g: Option<usize>,
-    omega_9_fat_100g: Option<usize>,
-    alpha_linolenic_acid_100g: Option<usize>,
-    eicosapentaenoic_acid_100g: Option<usize>,
-    docosahexaenoic_acid_100g: Option<usize>,
-    linoleic_acid_100g: Option<usize>,
-    arachidonic_acid_100g: Option<usize>,
-    gamma_linolenic_acid_100g: Option<usize>,
-    dihomo_gamma_linolenic_acid_100g: Option<usize>,
-    oleic_acid_100g: Option<usize>,
-    elaidic_acid_100g: Option<usize>,
-    gondoic_acid_100g: Option<usize>,
-    mead_acid_100g: Option<usize>,
-    erucic_acid_100g: Option<usize>,
-    nervonic_acid_100g: Option<usize>,
-    butyric_acid_100g: Option<usize>,
-    caproic_acid_100g: Option<usize>,
-    caprylic_acid_100g: Option<usize>,
-    capric_acid_100g: Option<usize>,
-    lauric_acid_100g: Option<usize>,
-    myristic_acid_100g: Option<usize>,
-    palmitic_acid_100g: Option<usize>,
-    stearic_acid_100g: Option<usize>,
-    arachidic_acid_100g: Option<usize>,
-    behenic_acid_100g: Option<usize>,
-    lignoceric_acid_100g: Option<usize>,
-    cerotic_acid_100g: Option<usize>,
-    montanic_acid_100g: Option<usize>,
-    melissic_acid_100g: Option<usize>,
-    // Other
-    caffeine_100g: Option<usize>,
-    taurine_100g: Option<usize>,
-    carnitine_100g: Option<usize>,
-    beta_glucan_100g: Option<usize>,
-    alcohol_100g: Option<usize>,
-    nucleotides_100g: Option<usize>,
-    casein_100g: Option<usize>,
-    serum_proteins_100g: Option<usize>,
-    methylsulfonylmethane_100g: Option<usize>,
-    energy_from_fat_100g: Option<usize>,
-    added_salt_100g: Option<usize>,
+    lang: Option<usize>,
+    allergens: Option<usize>,
+    traces: Option<usize>,
+    additives_tags: Option<usize>,
+    additives_n: Option<usize>,
+    labels_tags: Option<usize>,
+    ingredients_analysis_tags: Option<usize>,
+    unique_scans_n: Option<usize>,
+    popularity_key: Option<usize>,
+    // `product_name_{lang}` / `generic_name_{lang}` columns, keyed by language
+    localized_names: Vec<(String, usize)>,
+    localized_generic_names: Vec<(String, usize)>,
+    // One slot per registry nutrient
+    nutrients: Vec<Option<usize>>,
+    // Unrecognised `*_100g` columns, keyed by their output name
+    passthrough: Vec<(String, usize)>,
 }
 
 impl ColumnIndex {
-    fn from_headers(headers: &StringRecord) -> Self {
+    fn from_headers(headers: &StringRecord, registry: &NutrientRegistry, passthrough_unknown: bool) -> Self {
         let mut idx = ColumnIndex {
             product_name: None,
             generic_name: None,
@@ -349,116 +264,19 @@ impl ColumnIndex {
             countries: None,
             serving_size: None,
             serving_quantity: None,
-            energy_kcal_100g: None,
-            energy_kj_100g: None,
-            carbohydrates_100g: None,
-            fat_100g: None,
-            proteins_100g: None,
-            sugars_100g: None,
-            fiber_100g: None,
-            salt_100g: None,
-            added_sugars_100g: None,
-            sucrose_100g: None,
-            glucose_100g: None,
-            fructose_100g: None,
-            galactose_100g: None,
-            lactose_100g: None,
-            maltose_100g: None,
-            maltodextrins_100g: None,
-            psicose_100g: None,
-            starch_100g: None,
-            polyols_100g: None,
-            erythritol_100g: None,
-            isomalt_100g: None,
-            maltitol_100g: None,
-            sorbitol_100g: None,
-            soluble_fiber_100g: None,
-            insoluble_fiber_100g: None,
-            polydextrose_100g: None,
-            vitamin_a_100g: None,
-            beta_carotene_100g: None,
-            vitamin_d_100g: None,
-            vitamin_e_100g: None,
-            vitamin_k_100g: None,
-            vitamin_c_100g: None,
-            vitamin_b1_100g: None,
-            vitamin_b2_100g: None,
-            vitamin_pp_100g: None,
-            vitamin_b6_100g: None,
-            vitamin_b9_100g: None,
-            folates_100g: None,
-            vitamin_b12_100g: None,
-            biotin_100g: None,
-            pantothenic_acid_100g: None,
-            choline_100g: None,
-            phylloquinone_100g: None,
-            inositol_100g: None,
-            sodium_100g: None,
-            calcium_100g: None,
-            phosphorus_100g: None,
-            iron_100g: None,
-            magnesium_100g: None,
-            zinc_100g: None,
-            copper_100g: None,
-            manganese_100g: None,
-            fluoride_100g: None,
-            selenium_100g: None,
-            chromium_100g: None,
-            molybdenum_100g: None,
-            iodine_100g: None,
-            potassium_100g: None,
-            chloride_100g: None,
-            silica_100g: None,
-            bicarbonate_100g: None,
-            sulphate_100g: None,
-            nitrate_100g: None,
-            saturated_fat_100g: None,
-            unsaturated_fat_100g: None,
-            monounsaturated_fat_100g: None,
-            polyunsaturated_fat_100g: None,
-            trans_fat_100g: None,
-            cholesterol_100g: None,
-            omega_3_fat_100g: None,
-            omega_6_fat_100g: None,
-            omega_9_fat_100g: None,
-            alpha_linolenic_acid_100g: None,
-            eicosapentaenoic_acid_100g: None,
-            docosahexaenoic_acid_100g: None,
-            linoleic_acid_100g: None,
-            arachidonic_acid_100g: None,
-            gamma_linolenic_acid_100g: None,
-            dihomo_gamma_linolenic_acid_100g: None,
-            oleic_acid_100g: None,
-            elaidic_acid_100g: None,
-            gondoic_acid_100g: None,
-            mead_acid_100g: None,
-            erucic_acid_100g: None,
-            nervonic_acid_100g: None,
-            butyric_acid_100g: None,
-            caproic_acid_100g: None,
-            caprylic_acid_100g: None,
-            capric_acid_100g: None,
-            lauric_acid_100g: None,
-            myristic_acid_100g: None,
-            palmitic_acid_100g: None,
-            stearic_acid_100g: None,
-            arachidic_acid_100g: None,
-            behenic_acid_100g: None,
-            lignoceric_acid_100g: None,
-            cerotic_acid_100g: None,
-            montanic_acid_100g: None,
-            melissic_acid_100g: None,
-            caffeine_100g: None,
-            taurine_100g: None,
-            carnitine_100g: None,
-            beta_glucan_100g: None,
-            alcohol_100g: None,
-            nucleotides_100g: None,
-            casein_100g: None,
-            serum_proteins_100g: None,
-            methylsulfonylmethane_100g: None,
-            energy_from_fat_100g: None,
-            added_salt_100g: None,
+            lang: None,
+            allergens: None,
+            traces: None,
+            additives_tags: None,
+            additives_n: None,
+            labels_tags: None,
+            ingredients_analysis_tags: None,
+            unique_scans_n: None,
+            popularity_key: None,
+            localized_names: Vec::new(),
+            localized_generic_names: Vec::new(),
+            nutrients: vec![None; registry.defs().len()],
+            passthrough: Vec::new(),
         };
         for (i, header) in headers.iter().enumerate() {
             match header {
@@ -470,141 +288,41 @@ impl ColumnIndex {
                 "countries" => idx.countries = Some(i),
                 "serving_size" => idx.serving_size = Some(i),
                 "serving_quantity" => idx.serving_quantity = Some(i),
-                // Macros
-                "energy-kcal_100g" => idx.energy_kcal_100g = Some(i),
-                "energy-kj_100g" => idx.energy_kj_100g = Some(i),
-                "carbohydrates_100g" => idx.carbohydrates_100g = Some(i),
-                "fat_100g" => idx.fat_100g = Some(i),
-                "proteins_100g" => idx.proteins_100g = Some(i),
-                "sugars_100g" => idx.sugars_100g = Some(i),
-                "fiber_100g" => idx.fiber_100g = Some(i),
-                "salt_100g" => idx.salt_100g = Some(i),
-                "added-sugars_100g" => idx.added_sugars_100g = Some(i),
-                "sucrose_100g" => idx.sucrose_100g = Some(i),
-                "glucose_100g" => idx.glucose_100g = Some(i),
-                "fructose_100g" => idx.fructose_100g = Some(i),
-                "galactose_100g" => idx.galactose_100g = Some(i),
-                "lactose_100g" => idx.lactose_100g = Some(i),
-                "maltose_100g" => idx.maltose_100g = Some(i),
-                "maltodextrins_100g" => idx.maltodextrins_100g = Some(i),
-                "psicose_100g" => idx.psicose_100g = Some(i),
-                "starch_100g" => idx.starch_100g = Some(i),
-                "polyols_100g" => idx.polyols_100g = Some(i),
-                "erythritol_100g" => idx.erythritol_100g = Some(i),
-                "isomalt_100g" => idx.isomalt_100g = Some(i),
-                "maltitol_100g" => idx.maltitol_100g = Some(i),
-                "sorbitol_100g" => idx.sorbitol_100g = Some(i),
-                "soluble-fiber_100g" => idx.soluble_fiber_100g = Some(i),
-                "insoluble-fiber_100g" => idx.insoluble_fiber_100g = Some(i),
-                "polydextrose_100g" => idx.polydextrose_100g = Some(i),
-                // Vitamins
-                "vitamin-a_100g" => idx.vitamin_a_100g = Some(i),
-                "beta-carotene_100g" => idx.beta_carotene_100g = Some(i),
-                "vitamin-d_100g" => idx.vitamin_d_100g = Some(i),
-                "vitamin-e_100g" => idx.vitamin_e_100g = Some(i),
-                "vitamin-k_100g" => idx.vitamin_k_100g = Some(i),
-                "vitamin-c_100g" => idx.vitamin_c_100g = Some(i),
-                "vitamin-b1_100g" => idx.vitamin_b1_100g = Some(i),
-                "vitamin-b2_100g" => idx.vitamin_b2_100g = Some(i),
-                "vitamin-pp_100g" => idx.vitamin_pp_100g = Some(i),
-                "vitamin-b6_100g" => idx.vitamin_b6_100g = Some(i),
-                "vitamin-b9_100g" => idx.vitamin_b9_100g = Some(i),
-                "folates_100g" => idx.folates_100g = Some(i),
-                "vitamin-b12_100g" => idx.vitamin_b12_100g = Some(i),
-                "biotin_100g" => idx.biotin_100g = Some(i),
-                "pantothenic-acid_100g" => idx.pantothenic_acid_100g = Some(i),
-                "choline_100g" => idx.choline_100g = Some(i),
-                "phylloquinone_100g" => idx.phylloquinone_100g = Some(i),
-                "inositol_100g" => idx.inositol_100g = Some(i),
-                // Minerals
-                "sodium_100g" => idx.sodium_100g = Some(i),
-                "calcium_100g" => idx.calcium_100g = Some(i),
-                "phosphorus_100g" => idx.phosphorus_100g = Some(i),
-                "iron_100g" => idx.iron_100g = Some(i),
-                "magnesium_100g" => idx.magnesium_100g = Some(i),
-                "zinc_100g" => idx.zinc_100g = Some(i),
-                "copper_100g" => idx.copper_100g = Some(i),
-                "manganese_100g" => idx.manganese_100g = Some(i),
-                "fluoride_100g" => idx.fluoride_100g = Some(i),
-                "selenium_100g" => idx.selenium_100g = Some(i),
-                "chromium_100g" => idx.chromium_100g = Some(i),
-                "molybdenum_100g" => idx.molybdenum_100g = Some(i),
-                "iodine_100g" => idx.iodine_100g = Some(i),
-                "potassium_100g" => idx.potassium_100g = Some(i),
-                "chloride_100g" => idx.chloride_100g = Some(i),
-                "silica_100g" => idx.silica_100g = Some(i),
-                "bicarbonate_100g" => idx.bicarbonate_100g = Some(i),
-                "sulphate_100g" => idx.sulphate_100g = Some(i),
-                "nitrate_100g" => idx.nitrate_100g = Some(i),
-                // Fats
-                "saturated-fat_100g" => idx.saturated_fat_100g = Some(i),
-                "unsaturated-fat_100g" => idx.unsaturated_fat_100g = Some(i),
-                "monounsaturated-fat_100g" => idx.monounsaturated_fat_100g = Some(i),
-                "polyunsaturated-fat_100g" => idx.polyunsaturated_fat_100g = Some(i),
-                "trans-fat_100g" => idx.trans_fat_100g = Some(i),
-                "cholesterol_100g" => idx.cholesterol_100g = Some(i),
-                "omega-3-fat_100g" => idx.omega_3_fat_100g = Some(i),
-                "omega-6-fat_100g" => idx.omega_6_fat_100g = Some(i),
-                "omega-9-fat_100g" => idx.omega_9_fat_100g = Some(i),
-                "alpha-linolenic-acid_100g" => idx.alpha_linolenic_acid_100g = Some(i),
-                "eicosapentaenoic-acid_100g" => idx.eicosapentaenoic_acid_100g = Some(i),
-                "docosahexaenoic-acid_100g" => idx.docosahexaenoic_acid_100g = Some(i),
-                "linoleic-acid_100g" => idx.linoleic_acid_100g = Some(i),
-                "arachidonic-acid_100g" => idx.arachidonic_acid_100g = Some(i),
-                "gamma-linolenic-acid_100g" => idx.gamma_linolenic_acid_100g = Some(i),
-                "dihomo-gamma-linolenic-acid_100g" => idx.dihomo_gamma_linolenic_acid_100g = Some(i),
-                "oleic-acid_100g" => idx.oleic_acid_100g = Some(i),
-                "elaidic-acid_100g" => idx.elaidic_acid_100g = Some(i),
-                "gondoic-acid_100g" => idx.gondoic_acid_100g = Some(i),
-                "mead-acid_100g" => idx.mead_acid_100g = Some(i),
-                "erucic-acid_100g" => idx.erucic_acid_100g = Some(i),
-                "nervonic-acid_100g" => idx.nervonic_acid_100g = Some(i),
-                "butyric-acid_100g" => idx.butyric_acid_100g = Some(i),
-                "caproic-acid_100g" => idx.caproic_acid_100g = Some(i),
-                "caprylic-acid_100g" => idx.caprylic_acid_100g = Some(i),
-                "capric-acid_100g" => idx.capric_acid_100g = Some(i),
-                "lauric-acid_100g" => idx.lauric_acid_100g = Some(i),
-                "myristic-acid_100g" => idx.myristic_acid_100g = Some(i),
-                "palmitic-acid_100g" => idx.palmitic_acid_100g = Some(i),
-                "stearic-acid_100g" => idx.stearic_acid_100g = Some(i),
-                "arachidic-acid_100g" => idx.arachidic_acid_100g = Some(i),
-                "behenic-acid_100g" => idx.behenic_acid_100g = Some(i),
-                "lignoceric-acid_100g" => idx.lignoceric_acid_100g = Some(i),
-                "cerotic-acid_100g" => idx.cerotic_acid_100g = Some(i),
-                "montanic-acid_100g" => idx.montanic_acid_100g = Some(i),
-                "melissic-acid_100g" => idx.melissic_acid_100g = Some(i),
-                // Other
-                "caffeine_100g" => idx.caffeine_100g = Some(i),
-                "taurine_100g" => idx.taurine_100g = Some(i),
-                "carnitine_100g" => idx.carnitine_100g = Some(i),
-                "beta-glucan_100g" => idx.beta_glucan_100g = Some(i),
-                "alcohol_100g" => idx.alcohol_100g = Some(i),
-                "nucleotides_100g" => idx.nucleotides_100g = Some(i),
-                "casein_100g" => idx.casein_100g = Some(i),
-                "serum-proteins_100g" => idx.serum_proteins_100g = Some(i),
-                "methylsulfonylmethane_100g" => idx.methylsulfonylmethane_100g = Some(i),
-                "energy-from-fat_100g" => idx.energy_from_fat_100g = Some(i),
-                "added-salt_100g" => idx.added_salt_100g = Some(i),
-                _ => {}
+                "lang" => idx.lang = Some(i),
+                "allergens" => idx.allergens = Some(i),
+                "traces" => idx.traces = Some(i),
+                "additives_tags" => idx.additives_tags = Some(i),
+                "additives_n" => idx.additives_n = Some(i),
+                "labels_tags" => idx.labels_tags = Some(i),
+                "ingredients_analysis_tags" => idx.ingredients_analysis_tags = Some(i),
+                "unique_scans_n" => idx.unique_scans_n = Some(i),
+                "popularity_key" => idx.popularity_key = Some(i),
+                _ => {
+                    if let Some(lang) = languages::localized_column(header, "product_name") {
+                        idx.localized_names.push((lang.to_string(), i));
+                    } else if let Some(lang) = languages::localized_column(header, "generic_name") {
+                        idx.localized_generic_names.push((lang.to_string(), i));
+                    } else if let Some(slot) = registry.slot_for_column(header) {
+                        idx.nutrients[slot] = Some(i);
+                    } else if passthrough_unknown {
+                        if let Some(stem) = header.strip_suffix("_100g") {
+                            idx.passthrough.push((stem.replace('-', "_"), i));
+                        }
+                    }
+                }
             }
         }
         idx
     }
-}
-
-fn get_field<'a>(record: &'a StringRecord, idx: Option<usize>) -> Option<&'a str> {
-    idx.and_then(|i| record.get(i)).filter(|s| !s.is_empty())
-}
 
-// ---- Cached Regex ----
-fn serving_qty_regex() -> &'static Regex {
-    static RE: OnceLock<Regex> = OnceLock::new();
-    RE.get_or_init(|| Regex::new(r"([\d.,]+)\s*(g|gram|grams|ml|milliliter|milliliters)?").unwrap())
+    /// CSV column of a registry nutrient.
+    fn nutrient(&self, name: &str) -> Option<usize> {
+        nutrients::registry().slot(name).and_then(|slot| self.nutrients[slot])
+    }
 }
 
-fn serving_unit_regex() -> &'static Regex {
-    static RE: OnceLock<Regex> = OnceLock::new();
-    RE.get_or_init(|| Regex::new(r"\b(ml|milliliters?|g|grams?)\b").unwrap())
+fn get_field(record: &StringRecord, idx: Option<usize>) -> Option<&str> {
+    idx.and_then(|i| record.get(i)).filter(|s| !s.is_empty())
 }
 
 // ---- Helpers ----
@@ -628,6 +346,19 @@ fn write_product_file(product: &Product, code: &str) -> Result<()> {
     Ok(())
 }
 
+/// Publishes the nutrient registry so clients can label and scale `breakdown` values.
+fn write_nutrients_file(registry: &NutrientRegistry) -> Result<()> {
+    let path = Path::new(NUTRIENTS_FILE);
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create nutrients file: {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, registry.defs())
+        .with_context(|| format!("Failed to write nutrients file: {:?}", path))?;
+    writer.flush()
+        .with_context(|| format!("Failed to flush nutrients file: {:?}", path))?;
+    Ok(())
+}
+
 // ---- Country Cache ----
 fn build_country_cache() -> HashMap<String, String> {
     let mut cache = HashMap::new();
@@ -705,7 +436,7 @@ fn normalize_country_codes(countries_str: &str, cache: &HashMap<String, String>)
     let mut codes = Vec::new();
 
     let countries: Vec<&str> = countries_str
-        .split(|c| c == ',' || c == ';' || c == '|')
+        .split([',', ';', '|'])
         .map(|s| s.trim())
         .filter(|s| !s.is_empty())
         .collect();
@@ -772,31 +503,54 @@ fn map_country_to_iso_code(name: &str, cache: &HashMap<String, String>) -> Strin
     "unknown".to_string()
 }
 
-fn compress_catalog_file(jsonl_path: &Path, br_path: &Path) -> Result<()> {
-    let input_file = File::open(jsonl_path)
-        .with_context(|| format!("Failed to open JSONL file: {:?}", jsonl_path))?;
-    let mut reader = BufReader::new(input_file);
-
-    let output_file = File::create(br_path)
-        .with_context(|| format!("Failed to create compressed file: {:?}", br_path))?;
-    let mut writer = CompressorWriter::with_params(
-        output_file,
-        64 * 1024,
-        &BrotliEncoderParams::default(),
-    );
-
-    let mut buffer = [0u8; 64 * 1024];
-    loop {
-        let bytes_read = reader.read(&mut buffer)?;
-        if bytes_read == 0 {
-            break;
-        }
-        writer.write_all(&buffer[..bytes_read])?;
+/// Collects the non-empty localized columns into a `{lang: text}` map; the
+/// main column counts as the record's own language unless that column exists too.
+fn localized_texts(
+    record: &StringRecord,
+    columns: &[(String, usize)],
+    lang: Option<&str>,
+    main_text: Option<&str>,
+) -> BTreeMap<String, String> {
+    let mut texts: BTreeMap<String, String> = columns
+        .iter()
+        .filter_map(|(column_lang, idx)| {
+            let text = get_field(record, Some(*idx))?.trim();
+            (!text.is_empty()).then(|| (column_lang.clone(), text.to_string()))
+        })
+        .collect();
+    if let (Some(lang), Some(main_text)) = (lang, main_text) {
+        texts.entry(lang.to_string()).or_insert_with(|| main_text.to_string());
     }
+    texts
+}
 
-    writer.flush()?;
-    drop(writer);
-
+/// Compares a CSV header against the columns the processor reads, prints the
+/// summary and applies `--drift-report` and `--strict`.
+fn check_header_drift(headers: &StringRecord, args: &Args) -> Result<()> {
+    let expected_columns: Vec<&str> = TEXT_COLUMNS
+        .iter()
+        .copied()
+        .chain(nutrients::registry().defs().iter().map(|def| def.column.as_ref()))
+        .chain(headers.iter().filter(|header| {
+            languages::localized_column(header, "product_name").is_some()
+                || languages::localized_column(header, "generic_name").is_some()
+        }))
+        .collect();
+    let drift = DriftReport::new(headers, &expected_columns, REQUIRED_COLUMNS);
+    drift.print_summary();
+    if let Some(path) = &args.drift_report {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create drift report: {:?}", path))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut writer, &drift)
+            .with_context(|| format!("Failed to write drift report: {:?}", path))?;
+        writer.flush()
+            .with_context(|| format!("Failed to flush drift report: {:?}", path))?;
+        println!("Drift report written to {:?}", path);
+    }
+    if args.strict && !drift.missing_required.is_empty() {
+        anyhow::bail!("Required columns missing from input: {}", drift.missing_required.join(", "));
+    }
     Ok(())
 }
 
@@ -806,67 +560,88 @@ fn to_num(v: Option<&str>) -> Option<f64> {
     cleaned.parse().ok().filter(|n: &f64| n.is_finite())
 }
 
-fn parse_serving(record: &StringRecord, col_index: &ColumnIndex) -> (Option<f64>, Option<f64>, Option<String>) {
-    let raw_size_str = get_field(record, col_index.serving_size);
-    let raw_size = to_num(raw_size_str);
-    let mut qty = to_num(get_field(record, col_index.serving_quantity));
-
-    if qty.is_none() {
-        if let Some(size_str) = raw_size_str {
-            if let Some(captures) = serving_qty_regex().captures(size_str) {
-                if let Some(num_str) = captures.get(1) {
-                    let cleaned = num_str.as_str().replace(',', ".");
-                    if let Ok(parsed) = cleaned.parse::<f64>() {
-                        qty = Some(parsed);
-                    }
-                }
-            }
-        }
-    }
-
-    let unit = raw_size_str
-        .and_then(|size_str| {
-            serving_unit_regex().captures(size_str)
-                .and_then(|c| c.get(1))
-                .map(|m| m.as_str().to_lowercase())
-        });
-
-    (raw_size, qty, unit)
-}
-
 // ---- Main Processing ----
 fn main() -> Result<()> {
+    let args = Args::parse();
+
     println!("Starting OpenFoodFacts data processing...");
-    println!("Input file: {}", INPUT_FILE);
+    println!("Input file: {:?}", args.input);
     println!("Products directory: {}", PRODUCTS_DIR);
     println!("Catalogs directory: {}", CATALOG_BASE_DIR);
+    println!("Autocomplete directory: {}", AUTOCOMPLETE_BASE_DIR);
+    println!("Diets directory: {}", DIETS_BASE_DIR);
+    println!("Brands directory: {}", BRANDS_BASE_DIR);
+    println!("Category directory: {}", CATEGORY_BASE_DIR);
 
     println!("\nPhase 1: Setting up directories and streams...");
     ensure_dir(Path::new(PRODUCTS_DIR))?;
     ensure_dir(Path::new(CATALOG_BASE_DIR))?;
+    ensure_dir(Path::new(AUTOCOMPLETE_BASE_DIR))?;
+    ensure_dir(Path::new(DIETS_BASE_DIR))?;
+    ensure_dir(Path::new(BRANDS_BASE_DIR))?;
+    ensure_dir(Path::new(CATEGORY_BASE_DIR))?;
     println!("Directories created successfully");
 
-    let mut catalog_writers: HashMap<String, BufWriter<File>> = HashMap::new();
+    let catalog_writers: HashMap<String, BufWriter<File>> = HashMap::new();
+    let autocomplete = AutocompleteIndex::new(AUTOCOMPLETE_TOP_N);
+    let quality_summary = QualitySummary::default();
+    let diet_index = DietIndex::default();
+    let brand_index = PagedIndex::create(Path::new(BRANDS_BASE_DIR), PAGE_SIZE)?;
+    let category_index = PagedIndex::create(Path::new(CATEGORY_BASE_DIR), PAGE_SIZE)?;
+    let quality_policy = QualityPolicy::new(args.quality_default, &args.quality_policy);
 
     println!("Catalog writers initialized");
 
+    let registry = match &args.nutrients_config {
+        Some(path) => NutrientRegistry::with_config(path)?,
+        None => NutrientRegistry::builtin(),
+    };
+    println!("Nutrient registry loaded ({} nutrients)", registry.defs().len());
+    write_nutrients_file(&registry)?;
+    nutrients::init(registry);
+
+    let schema = Schema::new(args.catalog_rows, &args.catalog_columns)?;
+    println!("Catalog rows written as {:?} with {} columns", schema.row_format, schema.column_count());
+    catalog::init(schema);
+    catalog::write_header_file(Path::new(CATALOG_HEADER_FILE))?;
+
+    let taxonomy_path = args
+        .categories_taxonomy
+        .clone()
+        .or_else(|| Some(PathBuf::from(CATEGORIES_TAXONOMY_FILE)).filter(|path| path.exists()));
+    let taxonomy = match &taxonomy_path {
+        Some(path) => {
+            let taxonomy = Taxonomy::load(path)?;
+            println!("Category taxonomy loaded ({} categories)", taxonomy.category_count());
+            taxonomy
+        }
+        None => {
+            println!("No category taxonomy found; categories are indexed without ancestors");
+            Taxonomy::default()
+        }
+    };
+
     // Build country cache once
     let country_cache = build_country_cache();
     println!("Country cache built ({} entries)", country_cache.len());
 
     println!("\nPhase 2: Starting data processing pipeline...");
-    println!("Reading from: {}", INPUT_FILE);
-
-    let input_file = File::open(INPUT_FILE)
-        .with_context(|| format!("Failed to open input file: {}", INPUT_FILE))?;
-    let decoder = GzDecoder::new(input_file);
-    let mut reader = ReaderBuilder::new()
-        .delimiter(CSV_SEPARATOR)
-        .flexible(true)
-        .from_reader(decoder);
+    let (mut source, compression) = input::open(&args.input, args.input_format, TEXT_COLUMNS)?;
+    println!("Reading {:?} input ({:?} compression) from: {:?}", source.format(), compression, args.input);
 
     let mut processed_count: usize = 0;
-    let mut skipped_count: usize = 0;
+    let mut outputs = BatchOutputs {
+        catalog_writers,
+        autocomplete,
+        quality_summary,
+        diet_index,
+        brand_index,
+        category_index,
+        category_tree: CategoryTree::default(),
+        reject_log: RejectLog::create(Path::new(REJECTS_FILE))?,
+        sqlite: args.sqlite.as_deref().map(SqliteExport::create).transpose()?,
+        parquet: args.parquet.as_deref().map(ParquetExport::create).transpose()?,
+    };
     let start_time = Instant::now();
 
     let pb = ProgressBar::new(0);
@@ -878,33 +653,48 @@ fn main() -> Result<()> {
     );
     pb.set_message("Processing products...");
 
-    let headers = reader.headers()?.clone();
-    let col_index = ColumnIndex::from_headers(&headers);
+    let headers = source.headers().clone();
+
+    // A JSONL source builds its header from the columns it's asked for, so there's nothing to compare
+    if source.format() == InputFormat::Csv {
+        check_header_drift(&headers, &args)?;
+    } else if args.strict || args.drift_report.is_some() {
+        println!("Skipping header drift check: --strict and --drift-report only apply to CSV input");
+    }
+
+    let col_index = ColumnIndex::from_headers(
+        &headers,
+        nutrients::registry(),
+        args.passthrough_unknown_nutrients,
+    );
+    if !col_index.passthrough.is_empty() {
+        println!("Passing through {} unrecognised nutrient columns", col_index.passthrough.len());
+    }
 
-    let mut records = reader.records();
     let mut batch: Vec<StringRecord> = Vec::with_capacity(BATCH_SIZE);
 
-    while let Some(record) = records.next() {
+    while let Some(record) = source.next_record() {
         match record {
             Ok(record) => {
                 batch.push(record);
             }
             Err(e) => {
-                println!("Warning: Skipping malformed record: {}", e);
-                skipped_count += 1;
+                let rejection = Rejection::new(e.row, None, RejectReason::MalformedRow)
+                    .with_value("error", Some(&e.message));
+                outputs.reject_log.record(&rejection)?;
                 continue;
             }
         }
 
         if batch.len() >= BATCH_SIZE {
-            let (batch_processed, batch_skipped) = process_batch(
+            processed_count += process_batch(
                 &batch,
                 &col_index,
                 &country_cache,
-                &mut catalog_writers,
+                &quality_policy,
+                &taxonomy,
+                &mut outputs,
             )?;
-            processed_count += batch_processed;
-            skipped_count += batch_skipped;
             pb.set_position(processed_count as u64);
             batch.clear();
         }
@@ -912,14 +702,14 @@ fn main() -> Result<()> {
 
     // Process remaining records
     if !batch.is_empty() {
-        let (batch_processed, batch_skipped) = process_batch(
+        processed_count += process_batch(
             &batch,
             &col_index,
             &country_cache,
-            &mut catalog_writers,
+            &quality_policy,
+            &taxonomy,
+            &mut outputs,
         )?;
-        processed_count += batch_processed;
-        skipped_count += batch_skipped;
         pb.set_position(processed_count as u64);
     }
 
@@ -929,7 +719,20 @@ fn main() -> Result<()> {
     let total_time = start_time.elapsed().as_secs_f64();
     println!("Processing complete:");
     println!("   Processed: {} products", processed_count);
-    println!("   Skipped: {} rows", skipped_count);
+    let BatchOutputs {
+        mut catalog_writers,
+        autocomplete,
+        quality_summary,
+        diet_index,
+        brand_index,
+        category_index,
+        category_tree,
+        reject_log,
+        sqlite,
+        parquet,
+    } = outputs;
+    println!("   Skipped: {} rows (see {})", reject_log.total(), REJECTS_FILE);
+    reject_log.finish()?;
     println!("   Total time: {:.2}s", total_time);
     println!("   Average rate: {} products/sec",
             (processed_count as f64 / total_time) as usize);
@@ -947,85 +750,213 @@ fn main() -> Result<()> {
     };
     println!("   All catalog JSONL streams closed");
 
-    println!("   Compressing catalog files...");
+    println!("   Sorting and compressing catalog files...");
     country_codes.par_iter().for_each(|country_code| {
         let catalog_dir = Path::new(CATALOG_BASE_DIR).join(country_code);
-        let jsonl_path = catalog_dir.join("catalog.jsonl");
-        let br_path = catalog_dir.join("catalog.jsonl.br");
-
-        if jsonl_path.exists() {
-            if let Err(e) = compress_catalog_file(&jsonl_path, &br_path) {
-                eprintln!("Error compressing catalog for {}: {}", country_code, e);
+        let unsorted_path = catalog_dir.join(UNSORTED_CATALOG_FILE);
+
+        if unsorted_path.exists() {
+            let written = catalog::write_sorted(
+                &unsorted_path,
+                &catalog_dir,
+                country_code,
+                CATALOG_CHUNK_ROWS,
+                CATALOG_TOP_N,
+                CATALOG_SORT_RUN_ROWS,
+            );
+            if let Err(e) = written {
+                eprintln!("Error writing catalog for {}: {}", country_code, e);
             } else {
-                let _ = fs::remove_file(&jsonl_path);
+                let _ = fs::remove_file(&unsorted_path);
             }
         }
     });
     println!("   All catalog files compressed");
 
+    println!("   Writing quality summaries...");
+    quality_summary.write(Path::new(CATALOG_BASE_DIR))?;
+
+    println!("   Writing autocomplete shards...");
+    let shard_count = autocomplete.write(Path::new(AUTOCOMPLETE_BASE_DIR))?;
+    println!("   {} autocomplete shards written", shard_count);
+
+    println!("   Writing diet indexes...");
+    let diet_file_count = diet_index.write(Path::new(DIETS_BASE_DIR))?;
+    println!("   {} diet index files written", diet_file_count);
+
+    println!("   Writing brand index...");
+    let brand_count = brand_index.write()?;
+    println!("   {} brands indexed", brand_count);
+
+    println!("   Writing category index...");
+    let category_count = category_index.write()?;
+    category_tree.write(&Path::new(CATEGORY_BASE_DIR).join("_tree.json"), &taxonomy)?;
+    println!("   {} categories indexed", category_count);
+
+    if let Some(parquet) = parquet {
+        let partition_count = parquet.finish()?;
+        println!("   {} Parquet partitions written", partition_count);
+    }
+
+    if let Some(sqlite) = sqlite {
+        println!("   Indexing SQLite database...");
+        for path in sqlite.finish(&args.sqlite_country)? {
+            println!("   SQLite subset written to {:?}", path);
+        }
+    }
+
     println!("\nAll done! Data processing pipeline completed successfully.");
     println!("Check the following directories for results:");
     println!("   Products: {}", PRODUCTS_DIR);
     println!("   Catalogs: {}", CATALOG_BASE_DIR);
+    println!("   Autocomplete: {}", AUTOCOMPLETE_BASE_DIR);
+    println!("   Diets: {}", DIETS_BASE_DIR);
+    println!("   Brands: {}", BRANDS_BASE_DIR);
+    println!("   Categories: {}", CATEGORY_BASE_DIR);
 
     Ok(())
 }
 
+/// Everything `process_batch` writes to sequentially, across batches.
+struct BatchOutputs {
+    catalog_writers: HashMap<String, BufWriter<File>>,
+    autocomplete: AutocompleteIndex,
+    quality_summary: QualitySummary,
+    diet_index: DietIndex,
+    brand_index: PagedIndex,
+    category_index: PagedIndex,
+    category_tree: CategoryTree,
+    reject_log: RejectLog,
+    sqlite: Option<SqliteExport>,
+    parquet: Option<ParquetExport>,
+}
+
 fn process_batch(
     batch: &[StringRecord],
     col_index: &ColumnIndex,
     country_cache: &HashMap<String, String>,
-    catalog_writers: &mut HashMap<String, BufWriter<File>>,
-) -> Result<(usize, usize)> {
+    quality_policy: &QualityPolicy,
+    taxonomy: &Taxonomy,
+    outputs: &mut BatchOutputs,
+) -> Result<usize> {
+    let BatchOutputs {
+        catalog_writers,
+        autocomplete,
+        quality_summary,
+        diet_index,
+        brand_index,
+        category_index,
+        category_tree,
+        reject_log,
+        sqlite,
+        parquet,
+    } = outputs;
+
     // Parallel: parse records + write product files across all cores
-    let results: Vec<_> = batch.par_iter()
-        .filter_map(|record| {
-            match process_single_record(record, col_index, country_cache) {
-                Ok(Some(result)) => Some(result),
-                Ok(None) => None,
-                Err(e) => {
-                    eprintln!("Error processing record: {}", e);
-                    None
-                }
-            }
+    let outcomes: Vec<RecordResult> = batch.par_iter()
+        .map(|record| {
+            process_single_record(record, col_index, country_cache, quality_policy, taxonomy).unwrap_or_else(|e| {
+                let row = record.position().map(|p| p.line());
+                Err(Rejection::new(row, record.get(0), RejectReason::ProcessingError)
+                    .with_value("error", Some(&e.to_string())))
+            })
         })
         .collect();
 
+    let mut results = Vec::with_capacity(outcomes.len());
+    for outcome in outcomes {
+        match outcome {
+            Ok(result) => results.push(result),
+            Err(rejection) => reject_log.record(&rejection)?,
+        }
+    }
     let batch_processed = results.len();
-    let batch_skipped = batch.len() - batch_processed;
 
     // Sequential: write catalog entries (shared file handles)
-    for (catalog_entries, _brand) in &results {
-        for (catalog_entry, country_code) in catalog_entries {
-            if !catalog_writers.contains_key(country_code) {
-                let catalog_dir = Path::new(CATALOG_BASE_DIR).join(country_code);
+    for output in results {
+        // Brand pages list each product once, as its row in the first country's catalog
+        let mut index_row: Option<String> = None;
+
+        if sqlite.is_some() || parquet.is_some() {
+            let countries: Vec<&str> = output.catalog_entries.iter().map(|(_, country_code)| country_code.as_str()).collect();
+            if let Some(sqlite) = sqlite.as_mut() {
+                sqlite.add(&output.product, &countries, &output.brands)?;
+            }
+            if let Some(parquet) = parquet.as_mut() {
+                parquet.add(&output.product, &countries, &output.categories)?;
+            }
+        }
+
+        for (catalog_entry, country_code) in output.catalog_entries {
+            if !catalog_writers.contains_key(&country_code) {
+                let catalog_dir = Path::new(CATALOG_BASE_DIR).join(&country_code);
                 ensure_dir(&catalog_dir)?;
-                let catalog_path = catalog_dir.join("catalog.jsonl");
+                let catalog_path = catalog_dir.join(UNSORTED_CATALOG_FILE);
                 let catalog_file = File::create(&catalog_path)
                     .with_context(|| format!("Failed to create catalog file: {:?}", catalog_path))?;
                 let writer = BufWriter::with_capacity(64 * 1024, catalog_file);
                 catalog_writers.insert(country_code.clone(), writer);
             }
 
-            let writer = catalog_writers.get_mut(country_code).unwrap();
-            let line = serde_json::to_string(catalog_entry)
+            let writer = catalog_writers.get_mut(&country_code).unwrap();
+            let line = serde_json::to_string(&catalog_entry)
                 .with_context(|| "Failed to serialize catalog entry")?;
-            writeln!(writer, "{}", line)
+            writeln!(writer, "{}\t{}", output.popularity.spill_prefix(), line)
                 .with_context(|| "Failed to write catalog entry")?;
+            index_row.get_or_insert(line);
+
+            quality_summary.add(&country_code, &output.product.quality_issues);
+            diet_index.add(&country_code, &catalog_entry.code, &output.diets);
+            autocomplete.add(&country_code, catalog_entry);
+        }
+
+        if let Some(row) = &index_row {
+            for brand in &output.brands {
+                brand_index.add(&brand.key, &brand.name, row)?;
+            }
+            for key in &output.categories {
+                category_index.add(key, taxonomy.name(key), row)?;
+            }
+            category_tree.add(&output.categories);
         }
     }
 
-    Ok((batch_processed, batch_skipped))
+    if let Some(sqlite) = sqlite.as_mut() {
+        sqlite.commit_batch()?;
+    }
+    if let Some(parquet) = parquet.as_mut() {
+        parquet.write_batch()?;
+    }
+
+    Ok(batch_processed)
 }
 
+struct RecordOutput {
+    catalog_entries: Vec<(CatalogEntry, String)>,
+    // Kept for the SQLite and Parquet exports once its product file is written
+    product: Product,
+    diets: Vec<Diet>,
+    brands: Vec<Brand>,
+    // Main category key followed by its ancestors
+    categories: Vec<String>,
+    popularity: Popularity,
+}
+
+type RecordResult = std::result::Result<RecordOutput, Rejection>;
+
 fn process_single_record(
     record: &StringRecord,
     col_index: &ColumnIndex,
     country_cache: &HashMap<String, String>,
-) -> Result<Option<(Vec<(CatalogEntry, String)>, Option<String>)>> {
-    let code = record.get(0).unwrap_or("").replace(|c: char| !c.is_ascii_digit(), "");
+    quality_policy: &QualityPolicy,
+    taxonomy: &Taxonomy,
+) -> Result<RecordResult> {
+    let row = record.position().map(|p| p.line());
+    let raw_code = record.get(0);
+    let code = raw_code.unwrap_or("").replace(|c: char| !c.is_ascii_digit(), "");
     if code.is_empty() {
-        return Ok(None);
+        return Ok(Err(Rejection::new(row, None, RejectReason::EmptyCode)
+            .with_value("code", raw_code)));
     }
 
     let name = get_field(record, col_index.product_name).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
@@ -1033,178 +964,89 @@ fn process_single_record(
     let ingredients_text = get_field(record, col_index.ingredients_text).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
     let brand = get_field(record, col_index.brands).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
     let main_category = get_field(record, col_index.main_category).map(|s| s.trim().to_string()).filter(|s| !s.is_empty());
+    let lang = get_field(record, col_index.lang).and_then(languages::normalize);
+    let names = localized_texts(record, &col_index.localized_names, lang.as_deref(), name.as_deref());
+    let generic_names = localized_texts(record, &col_index.localized_generic_names, lang.as_deref(), generic_name.as_deref());
+    let allergen_tags = get_field(record, col_index.allergens).map(allergens::parse_tags).unwrap_or_default();
+    let trace_tags = get_field(record, col_index.traces).map(allergens::parse_tags).unwrap_or_default();
+    let additives = get_field(record, col_index.additives_tags).map(allergens::parse_tags).unwrap_or_default();
+    let additives_n = get_field(record, col_index.additives_n).and_then(|n| n.trim().parse().ok());
+    let labels = get_field(record, col_index.labels_tags).map(allergens::parse_tags).unwrap_or_default();
+    let analysis = get_field(record, col_index.ingredients_analysis_tags).map(allergens::parse_tags).unwrap_or_default();
+    let diets = diets::classify(&labels, &analysis);
+    let categories = main_category
+        .as_deref()
+        .and_then(|raw| taxonomy.resolve(raw))
+        .map(|key| taxonomy.with_ancestors(&key))
+        .unwrap_or_default();
     let countries_str = get_field(record, col_index.countries).unwrap_or("");
     let country_codes = normalize_country_codes(countries_str, country_cache);
 
-    let (_serving_size_raw, _serving_quantity, serving_unit) = parse_serving(record, col_index);
-
-    // Extract serving_size from serving_quantity fallback chain
-    let serving_size = to_num(get_field(record, col_index.serving_quantity))
-        .or_else(|| {
-            get_field(record, col_index.serving_size).and_then(|size_str| {
-                serving_qty_regex().captures(size_str)
-                    .and_then(|c| c.get(1))
-                    .and_then(|m| {
-                        let cleaned = m.as_str().replace(',', ".");
-                        cleaned.parse::<f64>().ok()
-                    })
-            })
-        });
+    // OFF's own serving_quantity is already normalized to g/ml, so it wins over our parse
+    let serving = get_field(record, col_index.serving_size).map(serving::parse).unwrap_or_default();
+    let serving_size = to_num(get_field(record, col_index.serving_quantity)).or(serving.quantity);
+    let serving_unit = serving.unit.map(|unit| unit.as_str().to_string());
 
-    // Validation: must have energy_kcal AND at least one of (carbohydrates, fat, proteins)
-    let energy_kcal = to_num(get_field(record, col_index.energy_kcal_100g));
-    let carbohydrates = to_num(get_field(record, col_index.carbohydrates_100g));
-    let fat = to_num(get_field(record, col_index.fat_100g));
-    let proteins = to_num(get_field(record, col_index.proteins_100g));
+    let values: Vec<Option<f64>> = col_index.nutrients
+        .iter()
+        .map(|&idx| to_num(get_field(record, idx)))
+        .collect();
+    let extra: BTreeMap<String, f64> = col_index.passthrough
+        .iter()
+        .filter_map(|(name, idx)| to_num(get_field(record, Some(*idx))).map(|v| (name.clone(), v)))
+        .collect();
+    let mut breakdown = Breakdown::new(values, extra);
 
-    if energy_kcal.is_none() || (carbohydrates.is_none() && fat.is_none() && proteins.is_none()) {
-        return Ok(None);
+    // Validation: must have energy_kcal AND at least one of (carbohydrates, fat, proteins)
+    let energy_kcal = breakdown.get("energy_kcal");
+    let carbohydrates = breakdown.get("carbohydrates");
+    let fat = breakdown.get("fat");
+    let proteins = breakdown.get("proteins");
+
+    if energy_kcal.is_none() {
+        return Ok(Err(Rejection::new(row, Some(&code), RejectReason::MissingEnergy)
+            .with_value("energy-kcal_100g", get_field(record, col_index.nutrient("energy_kcal")))));
+    }
+    if carbohydrates.is_none() && fat.is_none() && proteins.is_none() {
+        return Ok(Err(Rejection::new(row, Some(&code), RejectReason::NoMacros)
+            .with_value("carbohydrates_100g", get_field(record, col_index.nutrient("carbohydrates")))
+            .with_value("fat_100g", get_field(record, col_index.nutrient("fat")))
+            .with_value("proteins_100g", get_field(record, col_index.nutrient("proteins")))));
     }
 
-    // Build all nutrient sub-structs
-    let macros = MacroNutrients {
-        energy_kcal,
-        energy_kj: to_num(get_field(record, col_index.energy_kj_100g)),
-        carbohydrates,
-        fat,
-        proteins,
-        sugars: to_num(get_field(record, col_index.sugars_100g)),
-        fiber: to_num(get_field(record, col_index.fiber_100g)),
-        salt: to_num(get_field(record, col_index.salt_100g)),
-        added_sugars: to_num(get_field(record, col_index.added_sugars_100g)),
-        sucrose: to_num(get_field(record, col_index.sucrose_100g)),
-        glucose: to_num(get_field(record, col_index.glucose_100g)),
-        fructose: to_num(get_field(record, col_index.fructose_100g)),
-        galactose: to_num(get_field(record, col_index.galactose_100g)),
-        lactose: to_num(get_field(record, col_index.lactose_100g)),
-        maltose: to_num(get_field(record, col_index.maltose_100g)),
-        maltodextrins: to_num(get_field(record, col_index.maltodextrins_100g)),
-        psicose: to_num(get_field(record, col_index.psicose_100g)),
-        starch: to_num(get_field(record, col_index.starch_100g)),
-        polyols: to_num(get_field(record, col_index.polyols_100g)),
-        erythritol: to_num(get_field(record, col_index.erythritol_100g)),
-        isomalt: to_num(get_field(record, col_index.isomalt_100g)),
-        maltitol: to_num(get_field(record, col_index.maltitol_100g)),
-        sorbitol: to_num(get_field(record, col_index.sorbitol_100g)),
-        soluble_fiber: to_num(get_field(record, col_index.soluble_fiber_100g)),
-        insoluble_fiber: to_num(get_field(record, col_index.insoluble_fiber_100g)),
-        polydextrose: to_num(get_field(record, col_index.polydextrose_100g)),
-    };
-
-    let vitamins = Vitamins {
-        vitamin_a: to_num(get_field(record, col_index.vitamin_a_100g)),
-        beta_carotene: to_num(get_field(record, col_index.beta_carotene_100g)),
-        vitamin_d: to_num(get_field(record, col_index.vitamin_d_100g)),
-        vitamin_e: to_num(get_field(record, col_index.vitamin_e_100g)),
-        vitamin_k: to_num(get_field(record, col_index.vitamin_k_100g)),
-        vitamin_c: to_num(get_field(record, col_index.vitamin_c_100g)),
-        vitamin_b1: to_num(get_field(record, col_index.vitamin_b1_100g)),
-        vitamin_b2: to_num(get_field(record, col_index.vitamin_b2_100g)),
-        vitamin_pp: to_num(get_field(record, col_index.vitamin_pp_100g)),
-        vitamin_b6: to_num(get_field(record, col_index.vitamin_b6_100g)),
-        vitamin_b9: to_num(get_field(record, col_index.vitamin_b9_100g)),
-        folates: to_num(get_field(record, col_index.folates_100g)),
-        vitamin_b12: to_num(get_field(record, col_index.vitamin_b12_100g)),
-        biotin: to_num(get_field(record, col_index.biotin_100g)),
-        pantothenic_acid: to_num(get_field(record, col_index.pantothenic_acid_100g)),
-        choline: to_num(get_field(record, col_index.choline_100g)),
-        phylloquinone: to_num(get_field(record, col_index.phylloquinone_100g)),
-        inositol: to_num(get_field(record, col_index.inositol_100g)),
-    };
-
-    let minerals = Minerals {
-        sodium: to_num(get_field(record, col_index.sodium_100g)),
-        calcium: to_num(get_field(record, col_index.calcium_100g)),
-        phosphorus: to_num(get_field(record, col_index.phosphorus_100g)),
-        iron: to_num(get_field(record, col_index.iron_100g)),
-        magnesium: to_num(get_field(record, col_index.magnesium_100g)),
-        zinc: to_num(get_field(record, col_index.zinc_100g)),
-        copper: to_num(get_field(record, col_index.copper_100g)),
-        manganese: to_num(get_field(record, col_index.manganese_100g)),
-        fluoride: to_num(get_field(record, col_index.fluoride_100g)),
-        selenium: to_num(get_field(record, col_index.selenium_100g)),
-        chromium: to_num(get_field(record, col_index.chromium_100g)),
-        molybdenum: to_num(get_field(record, col_index.molybdenum_100g)),
-        iodine: to_num(get_field(record, col_index.iodine_100g)),
-        potassium: to_num(get_field(record, col_index.potassium_100g)),
-        chloride: to_num(get_field(record, col_index.chloride_100g)),
-        silica: to_num(get_field(record, col_index.silica_100g)),
-        bicarbonate: to_num(get_field(record, col_index.bicarbonate_100g)),
-        sulphate: to_num(get_field(record, col_index.sulphate_100g)),
-        nitrate: to_num(get_field(record, col_index.nitrate_100g)),
-    };
-
-    let fats = FatBreakdown {
-        saturated: to_num(get_field(record, col_index.saturated_fat_100g)),
-        unsaturated: to_num(get_field(record, col_index.unsaturated_fat_100g)),
-        monounsaturated: to_num(get_field(record, col_index.monounsaturated_fat_100g)),
-        polyunsaturated: to_num(get_field(record, col_index.polyunsaturated_fat_100g)),
-        trans: to_num(get_field(record, col_index.trans_fat_100g)),
-        cholesterol: to_num(get_field(record, col_index.cholesterol_100g)),
-        omega_3: to_num(get_field(record, col_index.omega_3_fat_100g)),
-        omega_6: to_num(get_field(record, col_index.omega_6_fat_100g)),
-        omega_9: to_num(get_field(record, col_index.omega_9_fat_100g)),
-        alpha_linolenic_acid: to_num(get_field(record, col_index.alpha_linolenic_acid_100g)),
-        eicosapentaenoic_acid: to_num(get_field(record, col_index.eicosapentaenoic_acid_100g)),
-        docosahexaenoic_acid: to_num(get_field(record, col_index.docosahexaenoic_acid_100g)),
-        linoleic_acid: to_num(get_field(record, col_index.linoleic_acid_100g)),
-        arachidonic_acid: to_num(get_field(record, col_index.arachidonic_acid_100g)),
-        gamma_linolenic_acid: to_num(get_field(record, col_index.gamma_linolenic_acid_100g)),
-        dihomo_gamma_linolenic_acid: to_num(get_field(record, col_index.dihomo_gamma_linolenic_acid_100g)),
-        oleic_acid: to_num(get_field(record, col_index.oleic_acid_100g)),
-        elaidic_acid: to_num(get_field(record, col_index.elaidic_acid_100g)),
-        gondoic_acid: to_num(get_field(record, col_index.gondoic_acid_100g)),
-        mead_acid: to_num(get_field(record, col_index.mead_acid_100g)),
-        erucic_acid: to_num(get_field(record, col_index.erucic_acid_100g)),
-        nervonic_acid: to_num(get_field(record, col_index.nervonic_acid_100g)),
-        butyric_acid: to_num(get_field(record, col_index.butyric_acid_100g)),
-        caproic_acid: to_num(get_field(record, col_index.caproic_acid_100g)),
-        caprylic_acid: to_num(get_field(record, col_index.caprylic_acid_100g)),
-        capric_acid: to_num(get_field(record, col_index.capric_acid_100g)),
-        lauric_acid: to_num(get_field(record, col_index.lauric_acid_100g)),
-        myristic_acid: to_num(get_field(record, col_index.myristic_acid_100g)),
-        palmitic_acid: to_num(get_field(record, col_index.palmitic_acid_100g)),
-        stearic_acid: to_num(get_field(record, col_index.stearic_acid_100g)),
-        arachidic_acid: to_num(get_field(record, col_index.arachidic_acid_100g)),
-        behenic_acid: to_num(get_field(record, col_index.behenic_acid_100g)),
-        lignoceric_acid: to_num(get_field(record, col_index.lignoceric_acid_100g)),
-        cerotic_acid: to_num(get_field(record, col_index.cerotic_acid_100g)),
-        montanic_acid: to_num(get_field(record, col_index.montanic_acid_100g)),
-        melissic_acid: to_num(get_field(record, col_index.melissic_acid_100g)),
-    };
-
-    let other = OtherNutrients {
-        caffeine: to_num(get_field(record, col_index.caffeine_100g)),
-        taurine: to_num(get_field(record, col_index.taurine_100g)),
-        carnitine: to_num(get_field(record, col_index.carnitine_100g)),
-        beta_glucan: to_num(get_field(record, col_index.beta_glucan_100g)),
-        alcohol: to_num(get_field(record, col_index.alcohol_100g)),
-        nucleotides: to_num(get_field(record, col_index.nucleotides_100g)),
-        casein: to_num(get_field(record, col_index.casein_100g)),
-        serum_proteins: to_num(get_field(record, col_index.serum_proteins_100g)),
-        methylsulfonylmethane: to_num(get_field(record, col_index.methylsulfonylmethane_100g)),
-        energy_from_fat: to_num(get_field(record, col_index.energy_from_fat_100g)),
-        added_salt: to_num(get_field(record, col_index.added_salt_100g)),
-    };
-
-    let breakdown = Breakdown {
-        macros,
-        vitamins,
-        minerals,
-        fats,
-        other,
+    let quality_issues = match quality::validate(&mut breakdown, quality_policy) {
+        Ok(issues) => issues,
+        Err(issue) => {
+            let mut rejection = Rejection::new(row, Some(&code), RejectReason::QualityCheck)
+                .with_value("check", Some(&issue.check.name()));
+            for (name, value) in &issue.values {
+                rejection = rejection.with_value(name, Some(&value.to_string()));
+            }
+            return Ok(Err(rejection));
+        }
     };
 
     let product = Product {
         code: code.clone(),
         product_name: name.clone(),
         generic_name,
+        ingredients: ingredients_text.as_deref().map(ingredients::parse).unwrap_or_default(),
         ingredients_text,
         brands: brand.clone(),
         main_category: main_category.clone(),
+        allergens: allergen_tags,
+        traces: trace_tags,
+        additives,
+        additives_n,
+        lang,
+        names,
+        generic_names,
         serving_size,
         serving_unit: serving_unit.clone(),
+        serving_description: serving.description,
+        serving_count: serving.count,
         breakdown,
+        quality_issues,
     };
 
     write_product_file(&product, &code)?;
@@ -1213,22 +1055,120 @@ fn process_single_record(
     let catalog_serving_size = serving_size.or(Some(100.0));
     let catalog_serving_unit = serving_unit.clone().or_else(|| Some("g".to_string()));
 
+    let nutrient_values = catalog::schema().nutrient_values(&product.breakdown);
+    let allergen_mask = allergens::allergen_mask(&product.allergens);
+    let trace_mask = allergens::allergen_mask(&product.traces);
+
     let mut catalog_entries = Vec::new();
     for country_code in &country_codes {
         let catalog_entry = CatalogEntry {
             code: code.clone(),
-            name: name.clone(),
+            name: languages::best_name(&product.names, country_code, name.as_deref()),
             brand: brand.clone(),
             country: Some(country_code.clone()),
             serving_size: catalog_serving_size,
             serving_unit: catalog_serving_unit.clone(),
-            fiber: product.breakdown.macros.fiber,
-            carbs: product.breakdown.macros.carbohydrates,
-            fat: product.breakdown.macros.fat,
-            protein: product.breakdown.macros.proteins,
+            nutrients: nutrient_values.clone(),
+            allergens: allergen_mask,
+            traces: trace_mask,
         };
         catalog_entries.push((catalog_entry, country_code.clone()));
     }
 
-    Ok(Some((catalog_entries, brand)))
+    Ok(Ok(RecordOutput {
+        catalog_entries,
+        diets,
+        brands: brand.as_deref().map(brands::canonicalize).unwrap_or_default(),
+        categories,
+        popularity: Popularity {
+            popularity_key: get_field(record, col_index.popularity_key).and_then(|v| v.trim().parse().ok()),
+            unique_scans_n: get_field(record, col_index.unique_scans_n).and_then(|v| v.trim().parse().ok()),
+        },
+        product,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn files_the_main_text_under_lang_unless_a_localized_column_has_one() {
+        // product_name_fr, product_name_de, product_name_it
+        let record = StringRecord::from(vec!["Chocolat noir", "Zartbitter", " "]);
+        let columns: Vec<(String, usize)> = ["fr", "de", "it"].iter().enumerate().map(|(i, lang)| (lang.to_string(), i)).collect();
+        let cases = [
+            // The localized column wins over the main one
+            (Some("fr"), Some("Dark chocolate"), vec![("de", "Zartbitter"), ("fr", "Chocolat noir")]),
+            // A blank localized column doesn't
+            (Some("it"), Some("Cioccolato"), vec![("de", "Zartbitter"), ("fr", "Chocolat noir"), ("it", "Cioccolato")]),
+            (Some("en"), Some("Dark chocolate"), vec![("de", "Zartbitter"), ("en", "Dark chocolate"), ("fr", "Chocolat noir")]),
+            (None, Some("Dark chocolate"), vec![("de", "Zartbitter"), ("fr", "Chocolat noir")]),
+            (Some("en"), None, vec![("de", "Zartbitter"), ("fr", "Chocolat noir")]),
+        ];
+        for (lang, main_text, expected) in cases {
+            let texts = localized_texts(&record, &columns, lang, main_text);
+            let texts: Vec<(&str, &str)> = texts.iter().map(|(l, t)| (l.as_str(), t.as_str())).collect();
+            assert_eq!(texts, expected, "{:?} {:?}", lang, main_text);
+        }
+    }
+
+    #[test]
+    fn passes_through_unknown_nutrient_columns_only_when_asked() {
+        let headers = StringRecord::from(vec!["code", "energy-kcal_100g", "added-calcium_100g", "product_name_fr", "serving_quantity", "nova_group"]);
+        let registry = nutrients::registry();
+
+        let without = ColumnIndex::from_headers(&headers, registry, false);
+        assert!(without.passthrough.is_empty());
+        assert_eq!(without.nutrients[registry.slot("energy_kcal").unwrap()], Some(1));
+
+        // Registry columns and non-nutrient columns are never passed through
+        let with = ColumnIndex::from_headers(&headers, registry, true);
+        assert_eq!(with.passthrough, [("added_calcium".to_string(), 2)]);
+        assert_eq!(with.nutrients[registry.slot("energy_kcal").unwrap()], Some(1));
+
+        // Passed-through values are published under `extra`
+        let extra = BTreeMap::from([("added_calcium".to_string(), 0.12)]);
+        let json = serde_json::to_value(Breakdown::new(vec![None; registry.defs().len()], extra)).unwrap();
+        assert_eq!(json["extra"], serde_json::json!({"added_calcium": 0.12}));
+    }
+
+    #[test]
+    fn rejects_records_with_the_reason_that_stopped_them() {
+        let headers = StringRecord::from(vec!["code", "product_name", "energy-kcal_100g", "fat_100g", "carbohydrates_100g", "sugars_100g"]);
+        let col_index = ColumnIndex::from_headers(&headers, nutrients::registry(), false);
+        let country_cache = HashMap::new();
+        let taxonomy = Taxonomy::default();
+        let reject_sugars = QualityPolicy::new(Policy::Flag, &[(QualityCheck::SugarsExceedCarbs, Policy::Reject)]);
+
+        let cases = [
+            (vec!["n/a", "Tea", "1", "0", "0", ""], RejectReason::EmptyCode, vec![("code", "n/a")]),
+            (vec!["1", "Tea", "", "0", "0", ""], RejectReason::MissingEnergy, vec![("energy-kcal_100g", "")]),
+            (vec!["1", "Tea", "abc", "0", "0", ""], RejectReason::MissingEnergy, vec![("energy-kcal_100g", "abc")]),
+            (
+                vec!["1", "Tea", "1", "", "", ""],
+                RejectReason::NoMacros,
+                vec![("carbohydrates_100g", ""), ("fat_100g", ""), ("proteins_100g", "")],
+            ),
+            (
+                vec!["1", "Jam", "250", "0", "50", "60"],
+                RejectReason::QualityCheck,
+                vec![("carbohydrates", "50"), ("check", "sugars_exceed_carbs"), ("sugars", "60")],
+            ),
+        ];
+        for (fields, reason, values) in cases {
+            let mut record = StringRecord::from(fields);
+            let mut position = csv::Position::new();
+            position.set_line(3);
+            record.set_position(Some(position));
+
+            let result = process_single_record(&record, &col_index, &country_cache, &reject_sugars, &taxonomy).unwrap();
+            // Rejections return before the product file is written
+            let Err(rejection) = result else { panic!("{:?} wasn't rejected", record) };
+            assert_eq!(rejection.reason, reason, "{:?}", record);
+            assert_eq!(rejection.row, Some(3));
+            let recorded: Vec<(&str, &str)> = rejection.values.iter().map(|(k, v)| (k.as_str(), v.as_str())).collect();
+            assert_eq!(recorded, values, "{:?}", record);
+        }
+    }
 }
@@ -0,0 +1,518 @@
+use anyhow::{bail, Context, Result};
+use serde::ser::SerializeMap;
+use serde::{Deserialize, Serialize, Serializer};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NutrientGroup {
+    Macros,
+    Vitamins,
+    Minerals,
+    Fats,
+    Other,
+}
+
+impl NutrientGroup {
+    const ALL: [NutrientGroup; 5] = [
+        NutrientGroup::Macros,
+        NutrientGroup::Vitamins,
+        NutrientGroup::Minerals,
+        NutrientGroup::Fats,
+        NutrientGroup::Other,
+    ];
+
+    fn key(self) -> &'static str {
+        match self {
+            NutrientGroup::Macros => "macros",
+            NutrientGroup::Vitamins => "vitamins",
+            NutrientGroup::Minerals => "minerals",
+            NutrientGroup::Fats => "fats",
+            NutrientGroup::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Unit {
+    #[serde(rename = "g")]
+    Gram,
+    #[serde(rename = "mg")]
+    Mg,
+    #[serde(rename = "µg")]
+    Ug,
+    #[serde(rename = "IU")]
+    Iu,
+    #[serde(rename = "kcal")]
+    Kcal,
+    #[serde(rename = "kJ")]
+    Kj,
+    #[serde(rename = "% vol")]
+    PercentVol,
+}
+
+impl Unit {
+    /// The serialized form, e.g. "µg".
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Unit::Gram => "g",
+            Unit::Mg => "mg",
+            Unit::Ug => "µg",
+            Unit::Iu => "IU",
+            Unit::Kcal => "kcal",
+            Unit::Kj => "kJ",
+            Unit::PercentVol => "% vol",
+        }
+    }
+
+    fn grams(self) -> Option<f64> {
+        match self {
+            Unit::Gram => Some(1.0),
+            Unit::Mg => Some(0.001),
+            Unit::Ug => Some(0.000_001),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NutrientDef {
+    /// Key in the product JSON, e.g. `vitamin_b12`
+    pub name: Cow<'static, str>,
+    /// OFF CSV column, e.g. `vitamin-b12_100g`
+    pub column: Cow<'static, str>,
+    pub group: NutrientGroup,
+    /// Unit of the per-100g value in the OFF export
+    pub unit: Unit,
+    /// Unit the value is published in; defaults to `unit`
+    #[serde(default)]
+    pub display_unit: Option<Unit>,
+    /// Multiplier from `unit` to `display_unit`; only needed for IU, mass units convert on their own
+    #[serde(default)]
+    pub display_factor: Option<f64>,
+    /// US Daily Value, in the display unit
+    #[serde(default)]
+    pub dv_us: Option<f64>,
+    /// EU Reference Intake, in the display unit
+    #[serde(default)]
+    pub dv_eu: Option<f64>,
+}
+
+impl NutrientDef {
+    /// The unit values are published in.
+    pub fn published_unit(&self) -> Unit {
+        self.display_unit.unwrap_or(self.unit)
+    }
+
+    /// Converts a value in `unit` to the published unit.
+    pub fn to_published(&self, value: f64) -> f64 {
+        round_to(value * self.display_factor(), 6)
+    }
+
+    fn display_factor(&self) -> f64 {
+        let mass_factor = || Some(self.unit.grams()? / self.display_unit?.grams()?);
+        self.display_factor.or_else(mass_factor).unwrap_or(1.0)
+    }
+}
+
+const fn nutrient(
+    name: &'static str,
+    column: &'static str,
+    group: NutrientGroup,
+    unit: Unit,
+    display_unit: Option<Unit>,
+    dv_us: Option<f64>,
+    dv_eu: Option<f64>,
+) -> NutrientDef {
+    NutrientDef {
+        name: Cow::Borrowed(name),
+        column: Cow::Borrowed(column),
+        group,
+        unit,
+        display_unit,
+        display_factor: None,
+        dv_us,
+        dv_eu,
+    }
+}
+
+use NutrientGroup::*;
+use Unit::{Gram, Kcal, Kj, Mg, PercentVol, Ug};
+
+// ---- Built-in Nutrients ----
+// Order is the key order in the product JSON.
+const BUILTIN_NUTRIENTS: &[NutrientDef] = &[
+    // Macros
+    nutrient("energy_kcal", "energy-kcal_100g", Macros, Kcal, None, Some(2000.0), Some(2000.0)),
+    nutrient("energy_kj", "energy-kj_100g", Macros, Kj, None, None, None),
+    nutrient("carbohydrates", "carbohydrates_100g", Macros, Gram, None, Some(275.0), Some(260.0)),
+    nutrient("fat", "fat_100g", Macros, Gram, None, Some(78.0), Some(70.0)),
+    nutrient("proteins", "proteins_100g", Macros, Gram, None, Some(50.0), Some(50.0)),
+    nutrient("sugars", "sugars_100g", Macros, Gram, None, None, Some(90.0)),
+    nutrient("fiber", "fiber_100g", Macros, Gram, None, Some(28.0), None),
+    nutrient("salt", "salt_100g", Macros, Gram, None, None, Some(6.0)),
+    nutrient("added_sugars", "added-sugars_100g", Macros, Gram, None, Some(50.0), None),
+    nutrient("sucrose", "sucrose_100g", Macros, Gram, None, None, None),
+    nutrient("glucose", "glucose_100g", Macros, Gram, None, None, None),
+    nutrient("fructose", "fructose_100g", Macros, Gram, None, None, None),
+    nutrient("galactose", "galactose_100g", Macros, Gram, None, None, None),
+    nutrient("lactose", "lactose_100g", Macros, Gram, None, None, None),
+    nutrient("maltose", "maltose_100g", Macros, Gram, None, None, None),
+    nutrient("maltodextrins", "maltodextrins_100g", Macros, Gram, None, None, None),
+    nutrient("psicose", "psicose_100g", Macros, Gram, None, None, None),
+    nutrient("starch", "starch_100g", Macros, Gram, None, None, None),
+    nutrient("polyols", "polyols_100g", Macros, Gram, None, None, None),
+    nutrient("erythritol", "erythritol_100g", Macros, Gram, None, None, None),
+    nutrient("isomalt", "isomalt_100g", Macros, Gram, None, None, None),
+    nutrient("maltitol", "maltitol_100g", Macros, Gram, None, None, None),
+    nutrient("sorbitol", "sorbitol_100g", Macros, Gram, None, None, None),
+    nutrient("soluble_fiber", "soluble-fiber_100g", Macros, Gram, None, None, None),
+    nutrient("insoluble_fiber", "insoluble-fiber_100g", Macros, Gram, None, None, None),
+    nutrient("polydextrose", "polydextrose_100g", Macros, Gram, None, None, None),
+    // Vitamins
+    nutrient("vitamin_a", "vitamin-a_100g", Vitamins, Gram, Some(Ug), Some(900.0), Some(800.0)),
+    nutrient("beta_carotene", "beta-carotene_100g", Vitamins, Gram, Some(Ug), None, None),
+    nutrient("vitamin_d", "vitamin-d_100g", Vitamins, Gram, Some(Ug), Some(20.0), Some(5.0)),
+    nutrient("vitamin_e", "vitamin-e_100g", Vitamins, Gram, Some(Mg), Some(15.0), Some(12.0)),
+    nutrient("vitamin_k", "vitamin-k_100g", Vitamins, Gram, Some(Ug), Some(120.0), Some(75.0)),
+    nutrient("vitamin_c", "vitamin-c_100g", Vitamins, Gram, Some(Mg), Some(90.0), Some(80.0)),
+    nutrient("vitamin_b1", "vitamin-b1_100g", Vitamins, Gram, Some(Mg), Some(1.2), Some(1.1)),
+    nutrient("vitamin_b2", "vitamin-b2_100g", Vitamins, Gram, Some(Mg), Some(1.3), Some(1.4)),
+    nutrient("vitamin_pp", "vitamin-pp_100g", Vitamins, Gram, Some(Mg), Some(16.0), Some(16.0)),
+    nutrient("vitamin_b6", "vitamin-b6_100g", Vitamins, Gram, Some(Mg), Some(1.7), Some(1.4)),
+    nutrient("vitamin_b9", "vitamin-b9_100g", Vitamins, Gram, Some(Ug), Some(400.0), Some(200.0)),
+    nutrient("folates", "folates_100g", Vitamins, Gram, Some(Ug), Some(400.0), Some(200.0)),
+    nutrient("vitamin_b12", "vitamin-b12_100g", Vitamins, Gram, Some(Ug), Some(2.4), Some(2.5)),
+    nutrient("biotin", "biotin_100g", Vitamins, Gram, Some(Ug), Some(30.0), Some(50.0)),
+    nutrient("pantothenic_acid", "pantothenic-acid_100g", Vitamins, Gram, Some(Mg), Some(5.0), Some(6.0)),
+    nutrient("choline", "choline_100g", Vitamins, Gram, Some(Mg), Some(550.0), None),
+    nutrient("phylloquinone", "phylloquinone_100g", Vitamins, Gram, Some(Ug), Some(120.0), Some(75.0)),
+    nutrient("inositol", "inositol_100g", Vitamins, Gram, Some(Mg), None, None),
+    // Minerals
+    nutrient("sodium", "sodium_100g", Minerals, Gram, Some(Mg), Some(2300.0), None),
+    nutrient("calcium", "calcium_100g", Minerals, Gram, Some(Mg), Some(1300.0), Some(800.0)),
+    nutrient("phosphorus", "phosphorus_100g", Minerals, Gram, Some(Mg), Some(1250.0), Some(700.0)),
+    nutrient("iron", "iron_100g", Minerals, Gram, Some(Mg), Some(18.0), Some(14.0)),
+    nutrient("magnesium", "magnesium_100g", Minerals, Gram, Some(Mg), Some(420.0), Some(375.0)),
+    nutrient("zinc", "zinc_100g", Minerals, Gram, Some(Mg), Some(11.0), Some(10.0)),
+    nutrient("copper", "copper_100g", Minerals, Gram, Some(Mg), Some(0.9), Some(1.0)),
+    nutrient("manganese", "manganese_100g", Minerals, Gram, Some(Mg), Some(2.3), Some(2.0)),
+    nutrient("fluoride", "fluoride_100g", Minerals, Gram, Some(Mg), None, Some(3.5)),
+    nutrient("selenium", "selenium_100g", Minerals, Gram, Some(Ug), Some(55.0), Some(55.0)),
+    nutrient("chromium", "chromium_100g", Minerals, Gram, Some(Ug), Some(35.0), Some(40.0)),
+    nutrient("molybdenum", "molybdenum_100g", Minerals, Gram, Some(Ug), Some(45.0), Some(50.0)),
+    nutrient("iodine", "iodine_100g", Minerals, Gram, Some(Ug), Some(150.0), Some(150.0)),
+    nutrient("potassium", "potassium_100g", Minerals, Gram, Some(Mg), Some(4700.0), Some(2000.0)),
+    nutrient("chloride", "chloride_100g", Minerals, Gram, Some(Mg), Some(2300.0), Some(800.0)),
+    nutrient("silica", "silica_100g", Minerals, Gram, Some(Mg), None, None),
+    nutrient("bicarbonate", "bicarbonate_100g", Minerals, Gram, Some(Mg), None, None),
+    nutrient("sulphate", "sulphate_100g", Minerals, Gram, Some(Mg), None, None),
+    nutrient("nitrate", "nitrate_100g", Minerals, Gram, Some(Mg), None, None),
+    // Fats
+    nutrient("saturated", "saturated-fat_100g", Fats, Gram, None, Some(20.0), Some(20.0)),
+    nutrient("unsaturated", "unsaturated-fat_100g", Fats, Gram, None, None, None),
+    nutrient("monounsaturated", "monounsaturated-fat_100g", Fats, Gram, None, None, None),
+    nutrient("polyunsaturated", "polyunsaturated-fat_100g", Fats, Gram, None, None, None),
+    nutrient("trans", "trans-fat_100g", Fats, Gram, None, None, None),
+    nutrient("cholesterol", "cholesterol_100g", Fats, Gram, None, Some(0.3), None),
+    nutrient("omega_3", "omega-3-fat_100g", Fats, Gram, None, None, None),
+    nutrient("omega_6", "omega-6-fat_100g", Fats, Gram, None, None, None),
+    nutrient("omega_9", "omega-9-fat_100g", Fats, Gram, None, None, None),
+    nutrient("alpha_linolenic_acid", "alpha-linolenic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("eicosapentaenoic_acid", "eicosapentaenoic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("docosahexaenoic_acid", "docosahexaenoic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("linoleic_acid", "linoleic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("arachidonic_acid", "arachidonic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("gamma_linolenic_acid", "gamma-linolenic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("dihomo_gamma_linolenic_acid", "dihomo-gamma-linolenic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("oleic_acid", "oleic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("elaidic_acid", "elaidic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("gondoic_acid", "gondoic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("mead_acid", "mead-acid_100g", Fats, Gram, None, None, None),
+    nutrient("erucic_acid", "erucic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("nervonic_acid", "nervonic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("butyric_acid", "butyric-acid_100g", Fats, Gram, None, None, None),
+    nutrient("caproic_acid", "caproic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("caprylic_acid", "caprylic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("capric_acid", "capric-acid_100g", Fats, Gram, None, None, None),
+    nutrient("lauric_acid", "lauric-acid_100g", Fats, Gram, None, None, None),
+    nutrient("myristic_acid", "myristic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("palmitic_acid", "palmitic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("stearic_acid", "stearic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("arachidic_acid", "arachidic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("behenic_acid", "behenic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("lignoceric_acid", "lignoceric-acid_100g", Fats, Gram, None, None, None),
+    nutrient("cerotic_acid", "cerotic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("montanic_acid", "montanic-acid_100g", Fats, Gram, None, None, None),
+    nutrient("melissic_acid", "melissic-acid_100g", Fats, Gram, None, None, None),
+    // Other
+    nutrient("caffeine", "caffeine_100g", Other, Gram, None, None, None),
+    nutrient("taurine", "taurine_100g", Other, Gram, None, None, None),
+    nutrient("carnitine", "carnitine_100g", Other, Gram, None, None, None),
+    nutrient("beta_glucan", "beta-glucan_100g", Other, Gram, None, None, None),
+    nutrient("alcohol", "alcohol_100g", Other, PercentVol, None, None, None),
+    nutrient("nucleotides", "nucleotides_100g", Other, Gram, None, None, None),
+    nutrient("casein", "casein_100g", Other, Gram, None, None, None),
+    nutrient("serum_proteins", "serum-proteins_100g", Other, Gram, None, None, None),
+    nutrient("methylsulfonylmethane", "methylsulfonylmethane_100g", Other, Gram, None, None, None),
+    nutrient("energy_from_fat", "energy-from-fat_100g", Other, Kj, None, None, None),
+    nutrient("added_salt", "added-salt_100g", Other, Gram, None, None, None),
+];
+
+pub struct NutrientRegistry {
+    defs: Vec<NutrientDef>,
+    by_name: HashMap<String, usize>,
+    by_column: HashMap<String, usize>,
+}
+
+impl NutrientRegistry {
+    pub fn builtin() -> Self {
+        let mut registry = Self {
+            defs: Vec::new(),
+            by_name: HashMap::new(),
+            by_column: HashMap::new(),
+        };
+        registry.extend(BUILTIN_NUTRIENTS.to_vec()).expect("built-in nutrient columns are unique");
+        registry
+    }
+
+    /// Built-in nutrients plus the definitions in a JSON config file.
+    ///
+    /// Entries whose `name` matches a built-in nutrient replace it, others are
+    /// appended. A column can only belong to one nutrient.
+    pub fn with_config(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read nutrients config: {:?}", path))?;
+        let defs: Vec<NutrientDef> = serde_json::from_str(&contents)
+            .with_context(|| format!("Failed to parse nutrients config: {:?}", path))?;
+
+        let mut registry = Self::builtin();
+        registry.extend(defs)
+            .with_context(|| format!("Invalid nutrients config: {:?}", path))?;
+        Ok(registry)
+    }
+
+    fn extend(&mut self, defs: Vec<NutrientDef>) -> Result<()> {
+        for def in defs {
+            let slot = self.by_name.get(def.name.as_ref()).copied();
+            // Taking over another nutrient's column would silently drop that nutrient's values
+            if let Some(owner) = self.by_column.get(def.column.as_ref()).filter(|owner| Some(**owner) != slot) {
+                bail!("Column {:?} of nutrient {:?} already belongs to {:?}", def.column, def.name, self.defs[*owner].name);
+            }
+            match slot {
+                Some(slot) => {
+                    self.by_column.remove(self.defs[slot].column.as_ref());
+                    self.by_column.insert(def.column.to_string(), slot);
+                    self.defs[slot] = def;
+                }
+                None => {
+                    let slot = self.defs.len();
+                    self.by_name.insert(def.name.to_string(), slot);
+                    self.by_column.insert(def.column.to_string(), slot);
+                    self.defs.push(def);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn defs(&self) -> &[NutrientDef] {
+        &self.defs
+    }
+
+    /// Registry slot for a CSV column.
+    pub fn slot_for_column(&self, column: &str) -> Option<usize> {
+        self.by_column.get(column).copied()
+    }
+
+    /// Registry slot for a nutrient name.
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.by_name.get(name).copied()
+    }
+}
+
+static REGISTRY: OnceLock<NutrientRegistry> = OnceLock::new();
+
+/// Installs the registry for this run. Must be called before any `Breakdown` is built.
+pub fn init(registry: NutrientRegistry) {
+    if REGISTRY.set(registry).is_err() {
+        panic!("nutrient registry initialized twice");
+    }
+}
+
+pub fn registry() -> &'static NutrientRegistry {
+    REGISTRY.get_or_init(NutrientRegistry::builtin)
+}
+
+/// Per-100g nutrient values, one slot per registry entry.
+#[derive(Debug)]
+pub struct Breakdown {
+    values: Vec<Option<f64>>,
+    // Unrecognised `*_100g` columns kept by `--passthrough-unknown-nutrients`
+    extra: BTreeMap<String, f64>,
+}
+
+impl Breakdown {
+    pub fn new(values: Vec<Option<f64>>, extra: BTreeMap<String, f64>) -> Self {
+        Self { values, extra }
+    }
+
+    pub fn get(&self, name: &str) -> Option<f64> {
+        registry().slot(name).and_then(|slot| self.values.get(slot).copied().flatten())
+    }
+
+    pub fn set(&mut self, name: &str, value: Option<f64>) {
+        if let Some(slot) = registry().slot(name) {
+            self.values[slot] = value;
+        }
+    }
+
+    /// Registry slot and value of every nutrient the product has.
+    pub fn present(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        self.values.iter().enumerate().filter_map(|(slot, value)| value.map(|v| (slot, v)))
+    }
+}
+
+/// A nutrient converted to its display unit, with its share of the reference intakes.
+#[derive(Serialize)]
+struct DisplayValue {
+    value: f64,
+    unit: Unit,
+    dv_us: Option<f64>,
+    dv_eu: Option<f64>,
+}
+
+impl DisplayValue {
+    fn new(def: &NutrientDef, value: f64) -> Self {
+        let value = def.to_published(value);
+        let percent_of = |reference: Option<f64>| {
+            reference.filter(|r| *r > 0.0).map(|r| round_to(value / r * 100.0, 1))
+        };
+        Self {
+            value,
+            unit: def.published_unit(),
+            dv_us: percent_of(def.dv_us),
+            dv_eu: percent_of(def.dv_eu),
+        }
+    }
+}
+
+fn round_to(value: f64, decimals: i32) -> f64 {
+    let scale = 10f64.powi(decimals);
+    (value * scale).round() / scale
+}
+
+struct GroupView<'a> {
+    breakdown: &'a Breakdown,
+    group: NutrientGroup,
+}
+
+impl Serialize for GroupView<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for (def, value) in registry().defs().iter().zip(&self.breakdown.values) {
+            if def.group != self.group {
+                continue;
+            }
+            let value = value.map(|value| DisplayValue::new(def, value));
+            map.serialize_entry(def.name.as_ref(), &value)?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Breakdown {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(None)?;
+        for group in NutrientGroup::ALL {
+            map.serialize_entry(group.key(), &GroupView { breakdown: self, group })?;
+        }
+        if !self.extra.is_empty() {
+            map.serialize_entry("extra", &self.extra)?;
+        }
+        map.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn every_present_nutrient_serializes_with_unit_and_intakes() {
+        let mut breakdown = Breakdown::new(vec![None; registry().defs().len()], BTreeMap::new());
+        breakdown.set("energy_kcal", Some(500.0));
+        breakdown.set("salt", Some(1.2));
+        breakdown.set("saturated", Some(4.0));
+        breakdown.set("vitamin_b12", Some(0.000_002_5));
+
+        let json = serde_json::to_value(&breakdown).unwrap();
+        assert_eq!(json["macros"]["energy_kcal"], json!({"value": 500.0, "unit": "kcal", "dv_us": 25.0, "dv_eu": 25.0}));
+        assert_eq!(json["macros"]["salt"], json!({"value": 1.2, "unit": "g", "dv_us": null, "dv_eu": 20.0}));
+        assert_eq!(json["fats"]["saturated"], json!({"value": 4.0, "unit": "g", "dv_us": 20.0, "dv_eu": 20.0}));
+        assert_eq!(json["vitamins"]["vitamin_b12"], json!({"value": 2.5, "unit": "µg", "dv_us": 104.2, "dv_eu": 100.0}));
+        // Missing nutrients stay null, and there's no `extra` without passthrough columns
+        assert_eq!(json["macros"]["fat"], Value::Null);
+        assert!(json.get("extra").is_none());
+    }
+
+    fn registry_with_config(name: &str, config: &str) -> Result<NutrientRegistry> {
+        let path = std::env::temp_dir().join(format!("nutrients-{}-{}.json", name, std::process::id()));
+        fs::write(&path, config).unwrap();
+        let registry = NutrientRegistry::with_config(&path);
+        fs::remove_file(&path).unwrap();
+        registry
+    }
+
+    #[test]
+    fn config_overrides_builtins_in_place_and_appends_new_nutrients() {
+        let builtin = NutrientRegistry::builtin();
+        let registry = registry_with_config(
+            "extend",
+            r#"[
+                {"name": "vitamin_a", "column": "vitamin-a-rae_100g", "group": "vitamins", "unit": "g", "display_unit": "µg", "dv_us": 700.0},
+                {"name": "vitamin_k2", "column": "vitamin-k2_100g", "group": "vitamins", "unit": "g", "display_unit": "µg"}
+            ]"#,
+        )
+        .unwrap();
+
+        // The override keeps its slot and moves to its new column
+        let slot = builtin.slot("vitamin_a").unwrap();
+        assert_eq!(registry.slot("vitamin_a"), Some(slot));
+        assert_eq!(registry.slot_for_column("vitamin-a-rae_100g"), Some(slot));
+        assert_eq!(registry.slot_for_column("vitamin-a_100g"), None);
+        assert_eq!(registry.defs()[slot].dv_us, Some(700.0));
+
+        let appended = builtin.defs().len();
+        assert_eq!(registry.defs().len(), appended + 1);
+        assert_eq!(registry.slot("vitamin_k2"), Some(appended));
+        assert_eq!(registry.slot_for_column("vitamin-k2_100g"), Some(appended));
+        assert_eq!(registry.defs()[appended].to_published(0.000_01), 10.0);
+    }
+
+    #[test]
+    fn config_cannot_take_another_nutrients_column() {
+        let clashes = [
+            // An appended nutrient reading a built-in column
+            r#"[{"name": "sodium_mg", "column": "sodium_100g", "group": "minerals", "unit": "g"}]"#,
+            // An override moving onto another built-in's column
+            r#"[{"name": "salt", "column": "sodium_100g", "group": "macros", "unit": "g"}]"#,
+            // Two appended nutrients sharing a column
+            r#"[
+                {"name": "vitamin_k2", "column": "vitamin-k2_100g", "group": "vitamins", "unit": "g"},
+                {"name": "menaquinone", "column": "vitamin-k2_100g", "group": "vitamins", "unit": "g"}
+            ]"#,
+        ];
+        for config in clashes {
+            let error = registry_with_config("clash", config).err().expect(config);
+            assert!(format!("{:#}", error).contains("already belongs to"), "{:#}", error);
+        }
+        // Overriding a nutrient without changing its column is fine
+        assert!(registry_with_config("same", r#"[{"name": "salt", "column": "salt_100g", "group": "macros", "unit": "g"}]"#).is_ok());
+    }
+}
@@ -0,0 +1,299 @@
+use anyhow::{Context, Result};
+use rayon::prelude::*;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use crate::ensure_dir;
+use crate::text;
+
+// Rows are spilled to this many bucket files while processing, so only one
+// bucket has to be grouped in memory at a time when the pages are written
+const SPILL_BUCKETS: usize = 64;
+// Longest key, in bytes; keys are directory names, which filesystems cap at
+// 255 bytes, and callers may prefix them (`fr-galettes`)
+const MAX_KEY_BYTES: usize = 96;
+// Hex digits of the hash that keeps truncated keys apart
+const KEY_HASH_DIGITS: usize = 8;
+
+#[derive(Serialize)]
+struct Meta<'a> {
+    key: &'a str,
+    name: &'a str,
+    count: usize,
+    page_size: usize,
+    pages: usize,
+}
+
+#[derive(Default)]
+struct KeyRows {
+    // Display spelling -> times seen; the most common one is published
+    names: HashMap<String, usize>,
+    rows: Vec<String>,
+}
+
+/// A keyed index of catalog rows, written as `{base_dir}/{shard}/{key}/`
+/// with a `_meta.json` and `page-0001.json`, `page-0002.json`, ...
+pub struct PagedIndex {
+    base_dir: PathBuf,
+    spill_dir: PathBuf,
+    page_size: usize,
+    buckets: Vec<Option<BufWriter<File>>>,
+}
+
+impl PagedIndex {
+    pub fn create(base_dir: &Path, page_size: usize) -> Result<Self> {
+        let spill_dir = base_dir.join("_spill");
+        ensure_dir(&spill_dir)?;
+        Ok(Self {
+            base_dir: base_dir.to_path_buf(),
+            spill_dir,
+            page_size,
+            buckets: (0..SPILL_BUCKETS).map(|_| None).collect(),
+        })
+    }
+
+    /// Appends a serialized catalog row under `key`; `name` is the key's display spelling.
+    pub fn add(&mut self, key: &str, name: &str, row: &str) -> Result<()> {
+        let bucket = bucket_for(key);
+        let writer = match &mut self.buckets[bucket] {
+            Some(writer) => writer,
+            slot => {
+                let path = self.spill_dir.join(format!("{:02}.tsv", bucket));
+                let file = File::create(&path)
+                    .with_context(|| format!("Failed to create index spill file: {:?}", path))?;
+                slot.insert(BufWriter::with_capacity(64 * 1024, file))
+            }
+        };
+        // Keys are slugs and serialized rows never contain raw tabs or newlines
+        let name = name.replace(['\t', '\n', '\r'], " ");
+        writeln!(writer, "{}\t{}\t{}", key, name, row)
+            .with_context(|| "Failed to write index spill file")?;
+        Ok(())
+    }
+
+    /// Writes every key's pages and metadata, then removes the spill files. Returns the key count.
+    pub fn write(self) -> Result<usize> {
+        let mut spill_paths = Vec::new();
+        for (bucket, writer) in self.buckets.into_iter().enumerate() {
+            if let Some(mut writer) = writer {
+                writer.flush()
+                    .with_context(|| "Failed to flush index spill file")?;
+                spill_paths.push(self.spill_dir.join(format!("{:02}.tsv", bucket)));
+            }
+        }
+
+        let base_dir = &self.base_dir;
+        let page_size = self.page_size;
+        let key_counts: Vec<usize> = spill_paths
+            .par_iter()
+            .map(|path| write_bucket(path, base_dir, page_size))
+            .collect::<Result<_>>()?;
+
+        fs::remove_dir_all(&self.spill_dir)
+            .with_context(|| format!("Failed to remove index spill directory: {:?}", self.spill_dir))?;
+        Ok(key_counts.iter().sum())
+    }
+}
+
+/// Turns a display name into a URL and directory safe key.
+///
+/// Case, accents and punctuation are ignored; apostrophes and dots join their
+/// neighbours, so "L'Oréal" becomes `loreal` and "Dr. Oetker" `dr-oetker`.
+/// Keys longer than `MAX_KEY_BYTES` are cut short and end in a hash of the
+/// full key, so they stay distinct.
+pub fn key_for(name: &str) -> String {
+    let folded: String = text::fold(name)
+        .chars()
+        .filter(|c| !matches!(c, '\'' | '’' | '.'))
+        .collect();
+    let key = folded
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-");
+    if key.len() <= MAX_KEY_BYTES {
+        return key;
+    }
+
+    // Sha256 rather than the std hasher, whose output may change between Rust releases
+    let hash = format!("{:x}", Sha256::digest(key.as_bytes()));
+    let mut end = MAX_KEY_BYTES - KEY_HASH_DIGITS - 1;
+    while !key.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}-{}", key[..end].trim_end_matches('-'), &hash[..KEY_HASH_DIGITS])
+}
+
+fn bucket_for(key: &str) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % SPILL_BUCKETS as u64) as usize
+}
+
+/// The shard directory of a key: its first two characters.
+fn shard_for(key: &str) -> String {
+    key.chars().take(2).collect()
+}
+
+fn is_all_caps(name: &str) -> bool {
+    name.chars().any(char::is_alphabetic) && !name.chars().any(char::is_lowercase)
+}
+
+fn write_bucket(spill_path: &Path, base_dir: &Path, page_size: usize) -> Result<usize> {
+    let file = File::open(spill_path)
+        .with_context(|| format!("Failed to open index spill file: {:?}", spill_path))?;
+
+    let mut keys: BTreeMap<String, KeyRows> = BTreeMap::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.with_context(|| format!("Failed to read index spill file: {:?}", spill_path))?;
+        let mut parts = line.splitn(3, '\t');
+        let (Some(key), Some(name), Some(row)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+        let entry = keys.entry(key.to_string()).or_default();
+        *entry.names.entry(name.to_string()).or_insert(0) += 1;
+        entry.rows.push(row.to_string());
+    }
+
+    let key_count = keys.len();
+    for (key, entry) in keys {
+        // Most common spelling; ties go to one that isn't shouted, then alphabetically
+        let name = entry
+            .names
+            .iter()
+            .max_by_key(|(name, count)| (**count, !is_all_caps(name), std::cmp::Reverse(name.as_str())))
+            .map(|(name, _)| name.as_str())
+            .unwrap_or(&key);
+
+        let key_dir = base_dir.join(shard_for(&key)).join(&key);
+        ensure_dir(&key_dir)?;
+
+        let pages: Vec<&[String]> = entry.rows.chunks(page_size.max(1)).collect();
+        for (i, rows) in pages.iter().enumerate() {
+            let page_path = key_dir.join(format!("page-{:04}.json", i + 1));
+            let file = File::create(&page_path)
+                .with_context(|| format!("Failed to create index page: {:?}", page_path))?;
+            let mut writer = BufWriter::new(file);
+            // Rows are already JSON, so the page array is assembled by hand
+            writer.write_all(b"[")?;
+            for (j, row) in rows.iter().enumerate() {
+                if j > 0 {
+                    writer.write_all(b",")?;
+                }
+                writer.write_all(row.as_bytes())?;
+            }
+            writer.write_all(b"]")?;
+            writer.flush()
+                .with_context(|| format!("Failed to flush index page: {:?}", page_path))?;
+        }
+
+        let meta = Meta {
+            key: &key,
+            name,
+            count: entry.rows.len(),
+            page_size,
+            pages: pages.len(),
+        };
+        let meta_path = key_dir.join("_meta.json");
+        let file = File::create(&meta_path)
+            .with_context(|| format!("Failed to create index metadata: {:?}", meta_path))?;
+        let mut writer = BufWriter::new(file);
+        serde_json::to_writer(&mut writer, &meta)
+            .with_context(|| format!("Failed to write index metadata: {:?}", meta_path))?;
+        writer.flush()
+            .with_context(|| format!("Failed to flush index metadata: {:?}", meta_path))?;
+    }
+
+    fs::remove_file(spill_path)
+        .with_context(|| format!("Failed to remove index spill file: {:?}", spill_path))?;
+    Ok(key_count)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn read_json(path: &Path) -> Value {
+        serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn keys_are_folded_slugs() {
+        assert_eq!(key_for("Dr. Oetker"), "dr-oetker");
+        assert_eq!(key_for("L’Oréal Paris"), "loreal-paris");
+        assert_eq!(key_for("Ben & Jerry's"), "ben-jerrys");
+        assert_eq!(key_for("  Müller  "), "muller");
+        assert_eq!(key_for("--"), "");
+    }
+
+    #[test]
+    fn long_keys_are_truncated_with_a_hash() {
+        let brand = "Extra ".repeat(50);
+        let key = key_for(&brand);
+        assert_eq!(brand.len(), 300);
+        assert!(key.len() <= MAX_KEY_BYTES);
+        assert!(key.starts_with("extra-extra-"));
+        assert_eq!(key_for(&brand), key);
+        // Names that only differ past the cut still get their own key
+        assert_ne!(key_for(&format!("{}Bio", brand)), key);
+        // Multibyte characters are never split
+        assert!(key_for(&"日本".repeat(100)).len() <= MAX_KEY_BYTES);
+
+        let base_dir = std::env::temp_dir().join(format!("paged-index-long-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base_dir);
+        let mut index = PagedIndex::create(&base_dir, 10).unwrap();
+        index.add(&key, brand.trim(), r#"{"code":"1"}"#).unwrap();
+        assert_eq!(index.write().unwrap(), 1);
+        assert_eq!(read_json(&base_dir.join("ex").join(&key).join("_meta.json"))["key"], key.as_str());
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn writes_pages_and_meta_per_key() {
+        let base_dir = std::env::temp_dir().join(format!("paged-index-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base_dir);
+
+        let mut index = PagedIndex::create(&base_dir, 2).unwrap();
+        index.add("nestle", "NESTLE", r#"{"code":"1"}"#).unwrap();
+        index.add("nestle", "Nestlé", r#"{"code":"2"}"#).unwrap();
+        index.add("nestle", "Nestlé", r#"{"code":"3"}"#).unwrap();
+        index.add("dr-oetker", "Dr.\tOetker", r#"{"code":"4"}"#).unwrap();
+        assert_eq!(index.write().unwrap(), 2);
+
+        let nestle = base_dir.join("ne/nestle");
+        assert_eq!(
+            read_json(&nestle.join("_meta.json")),
+            json!({"key": "nestle", "name": "Nestlé", "count": 3, "page_size": 2, "pages": 2})
+        );
+        assert_eq!(read_json(&nestle.join("page-0001.json")), json!([{"code": "1"}, {"code": "2"}]));
+        assert_eq!(read_json(&nestle.join("page-0002.json")), json!([{"code": "3"}]));
+        assert!(!nestle.join("page-0003.json").exists());
+
+        // Tabs in names can't break the spill format
+        assert_eq!(read_json(&base_dir.join("dr/dr-oetker/_meta.json"))["name"], "Dr. Oetker");
+        assert!(!base_dir.join("_spill").exists());
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+
+    #[test]
+    fn tied_spellings_prefer_one_that_isnt_all_caps() {
+        let base_dir = std::env::temp_dir().join(format!("paged-index-tie-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&base_dir);
+
+        let mut index = PagedIndex::create(&base_dir, 10).unwrap();
+        index.add("nestle", "NESTLE", r#"{"code":"1"}"#).unwrap();
+        index.add("nestle", "Nestlé", r#"{"code":"2"}"#).unwrap();
+        index.add("nestle", "Nestle", r#"{"code":"3"}"#).unwrap();
+        index.write().unwrap();
+
+        assert_eq!(read_json(&base_dir.join("ne/nestle/_meta.json"))["name"], "Nestle");
+        fs::remove_dir_all(&base_dir).unwrap();
+    }
+}
@@ -0,0 +1,364 @@
+use anyhow::{Context, Result};
+use arrow_array::builder::{Float64Builder, ListBuilder, StringBuilder, UInt32Builder};
+use arrow_array::cast::AsArray;
+use arrow_array::{ArrayRef, RecordBatch, RecordBatchReader};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+use parquet::arrow::arrow_reader::{ParquetRecordBatchReaderBuilder, RowSelection, RowSelector};
+use parquet::arrow::{ArrowWriter, ProjectionMask};
+use parquet::basic::{Compression, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::ensure_dir;
+use crate::nutrients;
+use crate::Product;
+
+// Most rows in one row group
+const ROW_GROUP_ROWS: usize = 50_000;
+// Encoded bytes buffered across every country's open row group; past this the
+// largest is flushed early, so memory doesn't grow with the number of countries
+const MAX_BUFFERED_BYTES: usize = 256 * 1024 * 1024;
+// Partition of products whose `countries` didn't resolve
+const UNKNOWN_COUNTRY: &str = "unknown";
+
+const TEXT_FIELDS: [&str; 9] = [
+    "code",
+    "product_name",
+    "generic_name",
+    "brands",
+    "main_category",
+    "lang",
+    "ingredients_text",
+    "serving_unit",
+    "serving_description",
+];
+// `categories` is the main category's index key followed by its ancestors
+const TAG_FIELDS: [&str; 5] = ["allergens", "traces", "additives", "categories", "quality_issues"];
+
+fn schema() -> SchemaRef {
+    let mut fields: Vec<Field> = TEXT_FIELDS
+        .iter()
+        .map(|name| Field::new(*name, DataType::Utf8, *name != "code"))
+        .collect();
+    fields.push(Field::new("serving_size", DataType::Float64, true));
+    fields.push(Field::new("serving_count", DataType::Float64, true));
+    fields.push(Field::new("additives_n", DataType::UInt32, true));
+    for name in TAG_FIELDS {
+        fields.push(Field::new_list(name, Field::new("item", DataType::Utf8, true), false));
+    }
+    // One flattened column per registry nutrient, e.g. `energy_kcal_100g`, with its unit as metadata
+    for def in nutrients::registry().defs() {
+        let metadata = HashMap::from([("unit".to_string(), def.unit.symbol().to_string())]);
+        fields.push(Field::new(format!("{}_100g", def.name), DataType::Float64, true).with_metadata(metadata));
+    }
+    Arc::new(Schema::new(fields))
+}
+
+/// Column builders for the rows of one country in the current batch.
+struct Columns {
+    text: Vec<StringBuilder>,
+    serving_size: Float64Builder,
+    serving_count: Float64Builder,
+    additives_n: UInt32Builder,
+    tags: Vec<ListBuilder<StringBuilder>>,
+    nutrients: Vec<Float64Builder>,
+    rows: usize,
+}
+
+impl Columns {
+    fn new(nutrient_count: usize) -> Self {
+        Self {
+            text: TEXT_FIELDS.iter().map(|_| StringBuilder::new()).collect(),
+            serving_size: Float64Builder::new(),
+            serving_count: Float64Builder::new(),
+            additives_n: UInt32Builder::new(),
+            tags: TAG_FIELDS.iter().map(|_| ListBuilder::new(StringBuilder::new())).collect(),
+            nutrients: (0..nutrient_count).map(|_| Float64Builder::new()).collect(),
+            rows: 0,
+        }
+    }
+
+    fn append(&mut self, product: &Product, categories: &[String]) {
+        let text = [
+            Some(product.code.as_str()),
+            product.product_name.as_deref(),
+            product.generic_name.as_deref(),
+            product.brands.as_deref(),
+            product.main_category.as_deref(),
+            product.lang.as_deref(),
+            product.ingredients_text.as_deref(),
+            product.serving_unit.as_deref(),
+            product.serving_description.as_deref(),
+        ];
+        for (builder, value) in self.text.iter_mut().zip(text) {
+            builder.append_option(value);
+        }
+        self.serving_size.append_option(product.serving_size);
+        self.serving_count.append_option(product.serving_count);
+        self.additives_n.append_option(product.additives_n);
+
+        let checks: Vec<String> = product.quality_issues.iter().map(|issue| issue.check.name().to_string()).collect();
+        let tags = [&product.allergens, &product.traces, &product.additives, categories, &checks];
+        for (builder, values) in self.tags.iter_mut().zip(tags) {
+            builder.append_value(values.iter().map(Some));
+        }
+
+        let mut values = vec![None; self.nutrients.len()];
+        for (slot, value) in product.breakdown.present() {
+            values[slot] = Some(value);
+        }
+        for (builder, value) in self.nutrients.iter_mut().zip(values) {
+            builder.append_option(value);
+        }
+        self.rows += 1;
+    }
+
+    /// Takes the buffered rows, leaving the builders empty.
+    fn finish(&mut self, schema: &SchemaRef) -> Result<RecordBatch> {
+        let mut arrays: Vec<ArrayRef> = self.text.iter_mut().map(|b| Arc::new(b.finish()) as ArrayRef).collect();
+        arrays.push(Arc::new(self.serving_size.finish()));
+        arrays.push(Arc::new(self.serving_count.finish()));
+        arrays.push(Arc::new(self.additives_n.finish()));
+        arrays.extend(self.tags.iter_mut().map(|b| Arc::new(b.finish()) as ArrayRef));
+        arrays.extend(self.nutrients.iter_mut().map(|b| Arc::new(b.finish()) as ArrayRef));
+        self.rows = 0;
+        RecordBatch::try_new(Arc::clone(schema), arrays).with_context(|| "Failed to build Parquet record batch")
+    }
+}
+
+struct Partition {
+    columns: Columns,
+    writer: ArrowWriter<File>,
+}
+
+/// Writes products as a Parquet dataset partitioned by country,
+/// `{dir}/country={cc}/part-0000.parquet`, one record batch per processing batch.
+///
+/// A repeated code replaces the earlier product, as in the SQLite export: its
+/// stale rows are dropped from the files once they're closed.
+pub struct ParquetExport {
+    dir: PathBuf,
+    schema: SchemaRef,
+    properties: WriterProperties,
+    partitions: HashMap<String, Partition>,
+    max_buffered_bytes: usize,
+    /// Hashes of every code added, to notice repeats without keeping the codes
+    seen: HashSet<u64>,
+    /// Partitions of the latest occurrence of each repeated code
+    repeated: HashMap<String, Vec<String>>,
+}
+
+impl ParquetExport {
+    pub fn create(dir: &Path) -> Result<Self> {
+        ensure_dir(dir)?;
+        let properties = WriterProperties::builder()
+            .set_compression(Compression::ZSTD(ZstdLevel::default()))
+            .set_max_row_group_size(ROW_GROUP_ROWS)
+            .build();
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            schema: schema(),
+            properties,
+            partitions: HashMap::new(),
+            max_buffered_bytes: MAX_BUFFERED_BYTES,
+            seen: HashSet::new(),
+            repeated: HashMap::new(),
+        })
+    }
+
+    /// Buffers a product in every country it's sold in.
+    pub fn add(&mut self, product: &Product, countries: &[&str], categories: &[String]) -> Result<()> {
+        let countries = if countries.is_empty() { &[UNKNOWN_COUNTRY][..] } else { countries };
+        let mut hasher = DefaultHasher::new();
+        product.code.hash(&mut hasher);
+        // A hash collision only costs a needless check of the code's rows in `finish`
+        if !self.seen.insert(hasher.finish()) {
+            let latest = countries.iter().map(|c| c.to_string()).collect();
+            self.repeated.insert(product.code.clone(), latest);
+        }
+        for country in countries {
+            if !self.partitions.contains_key(*country) {
+                let partition_dir = self.dir.join(format!("country={}", country));
+                ensure_dir(&partition_dir)?;
+                let path = partition_dir.join("part-0000.parquet");
+                let file = File::create(&path)
+                    .with_context(|| format!("Failed to create Parquet file: {:?}", path))?;
+                let writer = ArrowWriter::try_new(file, Arc::clone(&self.schema), Some(self.properties.clone()))
+                    .with_context(|| format!("Failed to create Parquet writer: {:?}", path))?;
+                let columns = Columns::new(nutrients::registry().defs().len());
+                self.partitions.insert(country.to_string(), Partition { columns, writer });
+            }
+            self.partitions.get_mut(*country).unwrap().columns.append(product, categories);
+        }
+        Ok(())
+    }
+
+    /// Hands the products buffered since the last call to the Parquet writers.
+    pub fn write_batch(&mut self) -> Result<()> {
+        for (country, partition) in self.partitions.iter_mut() {
+            if partition.columns.rows == 0 {
+                continue;
+            }
+            let batch = partition.columns.finish(&self.schema)?;
+            partition.writer.write(&batch)
+                .with_context(|| format!("Failed to write Parquet rows for country: {}", country))?;
+        }
+
+        let mut buffered: usize = self.partitions.values().map(|p| p.writer.in_progress_size()).sum();
+        while buffered > self.max_buffered_bytes {
+            let Some((country, partition)) = self.partitions.iter_mut().max_by_key(|(_, p)| p.writer.in_progress_size()) else {
+                break;
+            };
+            let size = partition.writer.in_progress_size();
+            if size == 0 {
+                break;
+            }
+            partition.writer.flush()
+                .with_context(|| format!("Failed to flush Parquet rows for country: {}", country))?;
+            buffered -= size;
+        }
+        Ok(())
+    }
+
+    /// Writes the remaining rows, closes every file and drops the rows of
+    /// replaced products. Returns the partition count.
+    pub fn finish(mut self) -> Result<usize> {
+        self.write_batch()?;
+        let partition_count = self.partitions.len();
+        let mut paths = Vec::with_capacity(partition_count);
+        for (country, partition) in self.partitions {
+            partition.writer.close()
+                .with_context(|| format!("Failed to close Parquet file for country: {}", country))?;
+            paths.push((self.dir.join(format!("country={}", country)).join("part-0000.parquet"), country));
+        }
+        if !self.repeated.is_empty() {
+            for (path, country) in &paths {
+                drop_replaced(path, country, &self.repeated, &self.properties)
+                    .with_context(|| format!("Failed to drop replaced products from Parquet file: {:?}", path))?;
+            }
+        }
+        Ok(partition_count)
+    }
+}
+
+/// Rewrites one partition without the rows superseded by a later occurrence
+/// of their code. Rows of a code keep their order within a partition, so the
+/// latest occurrence, if it's sold in this country, is the code's last row.
+fn drop_replaced(path: &Path, country: &str, repeated: &HashMap<String, Vec<String>>, properties: &WriterProperties) -> Result<()> {
+    let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?;
+    let code_only = ProjectionMask::roots(builder.parquet_schema(), [0]);
+    let mut codes = Vec::new();
+    for batch in builder.with_projection(code_only).build()? {
+        let batch = batch?;
+        // Only repeated codes matter; the rest are kept as `None`
+        let column = batch.column(0).as_string::<i32>();
+        codes.extend(column.iter().map(|code| repeated.get_key_value(code?).map(|(c, _)| c.as_str())));
+    }
+
+    let mut last_row = HashMap::new();
+    for (row, code) in codes.iter().enumerate() {
+        if let Some(code) = code {
+            last_row.insert(*code, row);
+        }
+    }
+    let keep: Vec<bool> = codes
+        .iter()
+        .enumerate()
+        .map(|(row, code)| match code {
+            Some(code) => last_row[code] == row && repeated[*code].iter().any(|c| c == country),
+            None => true,
+        })
+        .collect();
+    if keep.iter().all(|k| *k) {
+        return Ok(());
+    }
+
+    let mut selectors: Vec<RowSelector> = Vec::new();
+    for run in keep.chunk_by(|a, b| a == b) {
+        selectors.push(if run[0] { RowSelector::select(run.len()) } else { RowSelector::skip(run.len()) });
+    }
+    let reader = ParquetRecordBatchReaderBuilder::try_new(File::open(path)?)?
+        .with_row_selection(RowSelection::from(selectors))
+        .build()?;
+    let rewritten = path.with_extension("parquet.tmp");
+    let mut writer = ArrowWriter::try_new(File::create(&rewritten)?, reader.schema(), Some(properties.clone()))?;
+    for batch in reader {
+        writer.write(&batch?)?;
+    }
+    writer.close()?;
+    fs::rename(&rewritten, path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Each row's code and product name, in file order, plus the row group count.
+    fn read_rows(path: &Path) -> (Vec<(String, String)>, usize) {
+        let builder = ParquetRecordBatchReaderBuilder::try_new(File::open(path).unwrap()).unwrap();
+        let row_groups = builder.metadata().num_row_groups();
+        let mut rows = Vec::new();
+        for batch in builder.build().unwrap() {
+            let batch = batch.unwrap();
+            let codes = batch.column_by_name("code").unwrap().as_string::<i32>();
+            let names = batch.column_by_name("product_name").unwrap().as_string::<i32>();
+            rows.extend(codes.iter().zip(names.iter()).map(|(c, n)| (c.unwrap().to_string(), n.unwrap().to_string())));
+        }
+        (rows, row_groups)
+    }
+
+    fn rows(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs.iter().map(|(c, n)| (c.to_string(), n.to_string())).collect()
+    }
+
+    #[test]
+    fn partitions_by_country_and_keeps_the_latest_occurrence() {
+        let dir = std::env::temp_dir().join(format!("parquet-export-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut export = ParquetExport::create(&dir).unwrap();
+        export.add(&Product::named("1", "Crème brûlée"), &["fr", "de"], &[]).unwrap();
+        export.add(&Product::named("2", "Apfelstrudel"), &["de"], &[]).unwrap();
+        export.add(&Product::named("3", "Mystery"), &[], &[]).unwrap();
+        export.write_batch().unwrap();
+
+        // A repeated code replaces the product, even in countries it's no longer sold in
+        export.add(&Product::named("1", "Crème caramel"), &["fr"], &[]).unwrap();
+        export.add(&Product::named("4", "Tarte"), &["fr"], &[]).unwrap();
+        assert_eq!(export.finish().unwrap(), 3);
+
+        let partition = |country: &str| dir.join(format!("country={}", country)).join("part-0000.parquet");
+        assert_eq!(read_rows(&partition("fr")).0, rows(&[("1", "Crème caramel"), ("4", "Tarte")]));
+        assert_eq!(read_rows(&partition("de")).0, rows(&[("2", "Apfelstrudel")]));
+        assert_eq!(read_rows(&partition(UNKNOWN_COUNTRY)).0, rows(&[("3", "Mystery")]));
+        assert!(!partition("fr").with_extension("parquet.tmp").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn flushes_row_groups_past_the_buffer_budget() {
+        let dir = std::env::temp_dir().join(format!("parquet-budget-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let mut export = ParquetExport::create(&dir).unwrap();
+        export.max_buffered_bytes = 1;
+        for code in 1..=3 {
+            export.add(&Product::named(&code.to_string(), "Tea"), &["fr"], &[]).unwrap();
+            export.write_batch().unwrap();
+        }
+        export.finish().unwrap();
+
+        let (rows, row_groups) = read_rows(&dir.join("country=fr").join("part-0000.parquet"));
+        assert_eq!(rows.len(), 3);
+        assert_eq!(row_groups, 3);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
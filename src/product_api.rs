@@ -0,0 +1,346 @@
+use futures_util::stream;
+use http_body_util::{BodyExt, Full, Limited, StreamBody};
+use hyper::body::{Bytes, Frame, Incoming};
+use hyper::{header, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fs;
+use tokio::task::{self, JoinSet};
+use tracing::warn;
+
+use crate::barcode::{self, BarcodeError};
+use crate::projection;
+use crate::{json_response, ResponseBody, ServerState};
+
+// ---- Config ----
+const MAX_BATCH_BARCODES: usize = 100;
+const MAX_BATCH_BODY_BYTES: usize = 64 * 1024;
+const DEFAULT_SEARCH_PAGE_SIZE: usize = 20;
+const MAX_SEARCH_PAGE_SIZE: usize = 100;
+
+/// How a product should be shaped before it is returned (`?fields=` / `?compact=`).
+#[derive(Clone)]
+struct ProductView {
+    fields: Option<Vec<Vec<String>>>,
+    compact: bool,
+}
+
+impl ProductView {
+    fn from_params(params: &HashMap<String, String>) -> Result<Self, String> {
+        let fields = params.get("fields").map(|raw| projection::parse_fields(raw)).transpose()?;
+        let compact = matches!(params.get("compact").map(String::as_str), Some("1" | "true" | "yes"));
+        Ok(Self { fields, compact })
+    }
+
+    fn is_full(&self) -> bool {
+        self.fields.is_none() && !self.compact
+    }
+
+    fn apply(&self, mut product: Value) -> Value {
+        if let Some(paths) = &self.fields {
+            product = projection::project(&product, paths);
+        }
+        if self.compact {
+            projection::compact(&mut product);
+        }
+        product
+    }
+}
+
+enum Lookup {
+    Found { code: String, contents: Vec<u8> },
+    Invalid(BarcodeError),
+    NotFound { barcode: String, tried: Vec<String> },
+}
+
+/// Resolves a raw barcode against the static product store.
+fn lookup_product(state: &ServerState, raw_barcode: &str) -> std::io::Result<Lookup> {
+    let barcode = match barcode::sanitize(raw_barcode) {
+        Ok(barcode) => barcode,
+        Err(e) => return Ok(Lookup::Invalid(e)),
+    };
+
+    let candidates = barcode::candidates(&barcode);
+    match state.find_product(&candidates) {
+        Some((code, path)) => Ok(Lookup::Found { code, contents: fs::read(path)? }),
+        None => Ok(Lookup::NotFound { barcode, tried: candidates }),
+    }
+}
+
+fn invalid_view_response(reason: String) -> Response<Full<Bytes>> {
+    let body = json!({ "error": "Invalid fields parameter", "reason": reason });
+    json_response(StatusCode::BAD_REQUEST, body.to_string())
+}
+
+fn internal_error_response() -> Response<Full<Bytes>> {
+    json_response(StatusCode::INTERNAL_SERVER_ERROR, r#"{"error": "Internal server error"}"#)
+}
+
+pub fn handle_product_lookup(
+    state: &ServerState,
+    raw_barcode: &str,
+    params: &HashMap<String, String>,
+) -> Response<Full<Bytes>> {
+    let view = match ProductView::from_params(params) {
+        Ok(view) => view,
+        Err(reason) => return invalid_view_response(reason),
+    };
+
+    let (canonical_code, file_contents) = match lookup_product(state, raw_barcode) {
+        Ok(Lookup::Found { code, contents }) => (code, contents),
+        Ok(Lookup::Invalid(e)) => {
+            warn!("❌ Invalid barcode {:?}: {}", raw_barcode, e);
+            let body = json!({
+                "error": "Invalid barcode",
+                "barcode": raw_barcode,
+                "reason": e.to_string(),
+            });
+            return json_response(StatusCode::BAD_REQUEST, body.to_string());
+        }
+        Ok(Lookup::NotFound { barcode, tried }) => {
+            warn!("❌ Product not found: {} (tried {:?})", barcode, tried);
+            let body = json!({
+                "error": "Product not found",
+                "barcode": barcode,
+                "valid_check_digit": barcode::has_valid_check_digit(&barcode),
+                "tried": tried,
+            });
+            return json_response(StatusCode::NOT_FOUND, body.to_string());
+        }
+        Err(e) => {
+            warn!("❌ Error reading product {:?}: {}", raw_barcode, e);
+            return internal_error_response();
+        }
+    };
+
+    // Only decode the product when the client asked for a reduced view
+    let file_contents = if view.is_full() {
+        file_contents
+    } else {
+        match serde_json::from_slice(&file_contents) {
+            Ok(product) => view.apply(product).to_string().into_bytes(),
+            Err(e) => {
+                warn!("❌ Error parsing product {}: {}", canonical_code, e);
+                return internal_error_response();
+            }
+        }
+    };
+
+    state.log_bandwidth(&format!("{}.json", canonical_code), file_contents.len() as u64);
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .header(header::ACCESS_CONTROL_EXPOSE_HEADERS, "x-canonical-code")
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::CONTENT_LENGTH, file_contents.len().to_string())
+        .header("x-canonical-code", canonical_code)
+        .body(Full::new(Bytes::from(file_contents)))
+        .unwrap()
+}
+
+#[derive(Deserialize)]
+struct BatchRequest {
+    barcodes: Vec<String>,
+}
+
+/// Lookups for a batch, read concurrently on the blocking pool.
+struct BatchLookups {
+    barcodes: Vec<String>,
+    tasks: JoinSet<std::io::Result<Lookup>>,
+    positions: HashMap<task::Id, usize>,
+}
+
+impl BatchLookups {
+    fn spawn(state: &ServerState, barcodes: Vec<String>) -> Self {
+        let mut tasks = JoinSet::new();
+        let mut positions = HashMap::new();
+        for (i, raw_barcode) in barcodes.iter().enumerate() {
+            let state = state.clone();
+            let raw_barcode = raw_barcode.clone();
+            let handle = tasks.spawn_blocking(move || lookup_product(&state, &raw_barcode));
+            positions.insert(handle.id(), i);
+        }
+        Self { barcodes, tasks, positions }
+    }
+
+    /// Waits for the next lookup to finish, in completion order, with its position in the request.
+    /// A lookup whose task failed comes back as `None`.
+    async fn next(&mut self) -> Option<(usize, Option<std::io::Result<Lookup>>)> {
+        match self.tasks.join_next_with_id().await? {
+            Ok((id, lookup)) => Some((self.positions[&id], Some(lookup))),
+            Err(e) => {
+                warn!("❌ Batch lookup task failed: {}", e);
+                Some((self.positions[&e.id()], None))
+            }
+        }
+    }
+}
+
+/// Turns one lookup into either the (projected) product or a miss entry.
+fn batch_entry(
+    raw_barcode: &str,
+    lookup: Option<std::io::Result<Lookup>>,
+    view: &ProductView,
+    bytes_read: &mut u64,
+) -> Result<Value, Value> {
+    match lookup {
+        Some(Ok(Lookup::Found { code, contents })) => {
+            *bytes_read += contents.len() as u64;
+            match serde_json::from_slice::<Value>(&contents) {
+                Ok(product) => Ok(view.apply(product)),
+                Err(e) => {
+                    warn!("❌ Error parsing product {}: {}", code, e);
+                    Err(json!({ "barcode": raw_barcode, "reason": "error" }))
+                }
+            }
+        }
+        Some(Ok(Lookup::Invalid(e))) => {
+            Err(json!({ "barcode": raw_barcode, "reason": "invalid", "detail": e.to_string() }))
+        }
+        Some(Ok(Lookup::NotFound { tried, .. })) => {
+            Err(json!({ "barcode": raw_barcode, "reason": "not_found", "tried": tried }))
+        }
+        Some(Err(e)) => {
+            warn!("❌ Error reading product {:?}: {}", raw_barcode, e);
+            Err(json!({ "barcode": raw_barcode, "reason": "error" }))
+        }
+        None => Err(json!({ "barcode": raw_barcode, "reason": "error" })),
+    }
+}
+
+pub async fn handle_batch_lookup(
+    state: &ServerState,
+    body: Incoming,
+    params: &HashMap<String, String>,
+    wants_ndjson: bool,
+) -> Response<ResponseBody> {
+    let view = match ProductView::from_params(params) {
+        Ok(view) => view,
+        Err(reason) => return invalid_view_response(reason).map(BodyExt::boxed),
+    };
+
+    let body = match Limited::new(body, MAX_BATCH_BODY_BYTES).collect().await {
+        Ok(collected) => collected.to_bytes(),
+        Err(e) => {
+            warn!("❌ Error reading batch body: {}", e);
+            let body = json!({ "error": "Request body too large or unreadable", "max_bytes": MAX_BATCH_BODY_BYTES });
+            return json_response(StatusCode::PAYLOAD_TOO_LARGE, body.to_string()).map(BodyExt::boxed);
+        }
+    };
+
+    let request: BatchRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            let body = json!({ "error": "Invalid request body", "reason": e.to_string() });
+            return json_response(StatusCode::BAD_REQUEST, body.to_string()).map(BodyExt::boxed);
+        }
+    };
+
+    if request.barcodes.len() > MAX_BATCH_BARCODES {
+        let body = json!({
+            "error": "Too many barcodes",
+            "max_barcodes": MAX_BATCH_BARCODES,
+            "received": request.barcodes.len(),
+        });
+        return json_response(StatusCode::BAD_REQUEST, body.to_string()).map(BodyExt::boxed);
+    }
+
+    let lookups = BatchLookups::spawn(state, request.barcodes);
+    if wants_ndjson {
+        return ndjson_response(state.clone(), view, lookups);
+    }
+
+    // Restore request order so misses are listed the way they were asked for
+    let mut lookups = lookups;
+    let mut results: Vec<Option<std::io::Result<Lookup>>> = lookups.barcodes.iter().map(|_| None).collect();
+    while let Some((i, lookup)) = lookups.next().await {
+        results[i] = lookup;
+    }
+
+    let mut products = serde_json::Map::new();
+    let mut misses = Vec::new();
+    let mut bytes_read: u64 = 0;
+    for (raw_barcode, lookup) in lookups.barcodes.iter().zip(results) {
+        match batch_entry(raw_barcode, lookup, &view, &mut bytes_read) {
+            Ok(product) => {
+                products.insert(raw_barcode.clone(), product);
+            }
+            Err(miss) => misses.push(miss),
+        }
+    }
+
+    state.log_bandwidth("products:batch", bytes_read);
+
+    let body = json!({ "products": products, "misses": misses });
+    json_response(StatusCode::OK, body.to_string()).map(BodyExt::boxed)
+}
+
+/// Streams one NDJSON line per barcode as soon as its lookup completes, so lines come in
+/// completion order rather than request order.
+fn ndjson_response(state: ServerState, view: ProductView, lookups: BatchLookups) -> Response<ResponseBody> {
+    let lines = stream::unfold((lookups, 0u64), move |(mut lookups, mut bytes_read)| {
+        let state = state.clone();
+        let view = view.clone();
+        async move {
+            let Some((i, lookup)) = lookups.next().await else {
+                state.log_bandwidth("products:batch", bytes_read);
+                return None;
+            };
+            let raw_barcode = &lookups.barcodes[i];
+            let line = match batch_entry(raw_barcode, lookup, &view, &mut bytes_read) {
+                Ok(product) => json!({ "barcode": raw_barcode, "product": product }),
+                Err(miss) => miss,
+            };
+            let mut line = line.to_string();
+            line.push('\n');
+            Some((Ok::<_, Infallible>(Frame::data(Bytes::from(line))), (lookups, bytes_read)))
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(StreamBody::new(lines).boxed())
+        .unwrap()
+}
+
+pub fn handle_search(state: &ServerState, params: &HashMap<String, String>) -> Response<Full<Bytes>> {
+    let query = params.get("q").map(|q| q.trim()).unwrap_or("");
+    if query.is_empty() {
+        return json_response(StatusCode::BAD_REQUEST, r#"{"error": "Missing q parameter"}"#);
+    }
+
+    let page = params.get("page").and_then(|p| p.parse::<usize>().ok()).unwrap_or(1).max(1);
+    let page_size = params
+        .get("page_size")
+        .and_then(|p| p.parse::<usize>().ok())
+        .unwrap_or(DEFAULT_SEARCH_PAGE_SIZE)
+        .clamp(1, MAX_SEARCH_PAGE_SIZE);
+    let country = params.get("country").map(|c| c.to_lowercase()).filter(|c| !c.is_empty());
+
+    let Some(index) = state.search.get() else {
+        return json_response(StatusCode::SERVICE_UNAVAILABLE, r#"{"error": "Search index is loading"}"#);
+    };
+
+    if let Some(country) = &country {
+        if !index.has_country(country) {
+            let body = json!({ "error": "Unknown country", "country": country });
+            return json_response(StatusCode::NOT_FOUND, body.to_string());
+        }
+    }
+
+    let results = index.search(query, country.as_deref(), page - 1, page_size);
+    let body = json!({
+        "query": query,
+        "country": country,
+        "page": page,
+        "page_size": page_size,
+        "total": results.total,
+        "results": results.results,
+    });
+    json_response(StatusCode::OK, body.to_string())
+}
@@ -0,0 +1,129 @@
+use serde_json::{Map, Value};
+
+/// Parses a `fields` parameter such as `product_name,breakdown.macros` into dotted paths.
+pub fn parse_fields(raw: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut paths = Vec::new();
+    for field in raw.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+        let segments: Vec<String> = field.split('.').map(str::to_string).collect();
+        if segments.iter().any(|s| s.is_empty()) {
+            return Err(format!("invalid field path: {:?}", field));
+        }
+        paths.push(segments);
+    }
+    Ok(paths)
+}
+
+/// Keeps only the requested paths of a JSON document. Paths that don't exist are skipped.
+pub fn project(value: &Value, paths: &[Vec<String>]) -> Value {
+    let mut out = Value::Object(Map::new());
+    for path in paths {
+        if let Some(selected) = lookup(value, path) {
+            insert(&mut out, path, selected.clone());
+        }
+    }
+    out
+}
+
+/// Drops null fields, and objects left empty once their nulls are gone.
+pub fn compact(value: &mut Value) {
+    match value {
+        Value::Object(map) => {
+            for child in map.values_mut() {
+                compact(child);
+            }
+            map.retain(|_, v| !is_empty(v));
+        }
+        Value::Array(items) => items.iter_mut().for_each(compact),
+        _ => {}
+    }
+}
+
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::Object(map) => map.is_empty(),
+        _ => false,
+    }
+}
+
+fn lookup<'a>(value: &'a Value, path: &[String]) -> Option<&'a Value> {
+    path.iter().try_fold(value, |current, key| current.get(key))
+}
+
+fn insert(target: &mut Value, path: &[String], selected: Value) {
+    let Some((last, parents)) = path.split_last() else {
+        return;
+    };
+
+    let mut current = target;
+    for key in parents {
+        let Some(map) = current.as_object_mut() else {
+            return;
+        };
+        current = map.entry(key.clone()).or_insert_with(|| Value::Object(Map::new()));
+    }
+    if let Some(map) = current.as_object_mut() {
+        map.insert(last.clone(), selected);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn product() -> Value {
+        json!({
+            "code": "3017620422003",
+            "product_name": "Nutella",
+            "brands": null,
+            "breakdown": {
+                "macros": {"energy_kcal": 539.0, "fat": 30.9},
+                "vitamins": {"vitamin_c": null},
+            },
+        })
+    }
+
+    #[test]
+    fn parses_dotted_paths() {
+        assert_eq!(
+            parse_fields(" product_name, breakdown.macros.fat ,,").unwrap(),
+            vec![vec!["product_name".to_string()], vec!["breakdown".into(), "macros".into(), "fat".into()]]
+        );
+        assert!(parse_fields("").unwrap().is_empty());
+        assert!(parse_fields("breakdown..fat").is_err());
+        assert!(parse_fields("breakdown.").is_err());
+    }
+
+    #[test]
+    fn projects_nested_paths_and_skips_missing_ones() {
+        let paths = parse_fields("code,breakdown.macros.fat,breakdown.minerals.iron,nope").unwrap();
+        assert_eq!(
+            project(&product(), &paths),
+            json!({"code": "3017620422003", "breakdown": {"macros": {"fat": 30.9}}})
+        );
+    }
+
+    #[test]
+    fn overlapping_paths_merge() {
+        let paths = parse_fields("breakdown.macros,breakdown.macros.fat,breakdown.vitamins").unwrap();
+        assert_eq!(
+            project(&product(), &paths),
+            json!({"breakdown": {"macros": {"energy_kcal": 539.0, "fat": 30.9}, "vitamins": {"vitamin_c": null}}})
+        );
+    }
+
+    #[test]
+    fn compact_drops_nulls_and_emptied_objects() {
+        let mut value = product();
+        compact(&mut value);
+        assert_eq!(
+            value,
+            json!({
+                "code": "3017620422003",
+                "product_name": "Nutella",
+                "breakdown": {"macros": {"energy_kcal": 539.0, "fat": 30.9}},
+            })
+        );
+    }
+}
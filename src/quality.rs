@@ -0,0 +1,409 @@
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use crate::ensure_dir;
+use crate::nutrients::Breakdown;
+
+// kcal per gram (Atwater general factors)
+const KCAL_PER_G_CARBOHYDRATE: f64 = 4.0;
+const KCAL_PER_G_PROTEIN: f64 = 4.0;
+const KCAL_PER_G_FAT: f64 = 9.0;
+const KCAL_PER_G_FIBER: f64 = 2.0;
+const KCAL_PER_KJ: f64 = 0.239;
+const SALT_PER_G_SODIUM: f64 = 2.5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, ValueEnum)]
+#[serde(rename_all = "snake_case")]
+#[value(rename_all = "snake_case")]
+pub enum QualityCheck {
+    /// Sugars above total carbohydrates
+    SugarsExceedCarbs,
+    /// Saturated fat above total fat
+    SaturatedExceedsFat,
+    /// Fat, carbohydrates, proteins and salt add up to more than 100g
+    #[serde(rename = "macro_sum_over_100")]
+    #[value(name = "macro_sum_over_100")]
+    MacroSumOver100,
+    /// Declared kcal far from the Atwater estimate
+    EnergyAtwaterMismatch,
+    /// Declared kcal and kJ disagree
+    EnergyKjMismatch,
+    /// Salt isn't 2.5 × sodium
+    SaltSodiumMismatch,
+}
+
+impl QualityCheck {
+    /// The snake_case name used on the command line and in JSON.
+    pub fn name(self) -> String {
+        self.to_possible_value().map(|v| v.get_name().to_string()).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Policy {
+    /// Don't run the check
+    Ignore,
+    /// Publish as-is and record the issue on the product
+    Flag,
+    /// Fix the value where a repair exists, otherwise flag
+    Repair,
+    /// Drop the record
+    Reject,
+}
+
+/// Policy per check; checks without an override use the default.
+pub struct QualityPolicy {
+    default: Policy,
+    overrides: HashMap<QualityCheck, Policy>,
+}
+
+impl QualityPolicy {
+    pub fn new(default: Policy, overrides: &[(QualityCheck, Policy)]) -> Self {
+        Self {
+            default,
+            overrides: overrides.iter().copied().collect(),
+        }
+    }
+
+    fn for_check(&self, check: QualityCheck) -> Policy {
+        self.overrides.get(&check).copied().unwrap_or(self.default)
+    }
+}
+
+/// Parses a `--quality-policy check=policy` argument.
+pub fn parse_policy_override(raw: &str) -> Result<(QualityCheck, Policy), String> {
+    let (check, policy) = raw
+        .split_once('=')
+        .ok_or_else(|| format!("expected CHECK=POLICY, got {:?}", raw))?;
+    let check = QualityCheck::from_str(check.trim(), true)?;
+    let policy = Policy::from_str(policy.trim(), true)?;
+    Ok((check, policy))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueAction {
+    Flagged,
+    Repaired,
+}
+
+/// The values a failed check looked at, keyed by nutrient.
+type CheckValues = BTreeMap<&'static str, f64>;
+type CheckFn = fn(&mut Breakdown, bool) -> Option<CheckValues>;
+
+#[derive(Debug, Serialize)]
+pub struct QualityIssue {
+    pub check: QualityCheck,
+    pub action: IssueAction,
+    /// The values the check looked at, before any repair
+    pub values: CheckValues,
+}
+
+/// Runs every enabled check against a breakdown, repairing it in place where the policy asks.
+///
+/// Returns the failed check when a `reject` policy fires.
+pub fn validate(breakdown: &mut Breakdown, policy: &QualityPolicy) -> Result<Vec<QualityIssue>, QualityIssue> {
+    let mut issues = Vec::new();
+
+    let checks: [(QualityCheck, CheckFn); 6] = [
+        (QualityCheck::SugarsExceedCarbs, sugars_exceed_carbs),
+        (QualityCheck::SaturatedExceedsFat, saturated_exceeds_fat),
+        (QualityCheck::MacroSumOver100, macro_sum_over_100),
+        (QualityCheck::EnergyAtwaterMismatch, energy_atwater_mismatch),
+        (QualityCheck::EnergyKjMismatch, energy_kj_mismatch),
+        (QualityCheck::SaltSodiumMismatch, salt_sodium_mismatch),
+    ];
+
+    for (check, run) in checks {
+        let policy = policy.for_check(check);
+        if policy == Policy::Ignore {
+            continue;
+        }
+
+        // Only `repair` lets a check touch the breakdown; `None` means the check passed
+        let repair = policy == Policy::Repair;
+        let Some(values) = run(breakdown, repair) else {
+            continue;
+        };
+
+        let repaired = repair && has_repair(check);
+        let action = if repaired { IssueAction::Repaired } else { IssueAction::Flagged };
+        let issue = QualityIssue { check, action, values };
+
+        if policy == Policy::Reject {
+            return Err(issue);
+        }
+        issues.push(issue);
+    }
+
+    Ok(issues)
+}
+
+fn has_repair(check: QualityCheck) -> bool {
+    !matches!(check, QualityCheck::MacroSumOver100 | QualityCheck::EnergyAtwaterMismatch)
+}
+
+/// Relative disagreement with an absolute floor, so tiny values don't trip the checks.
+fn disagrees(declared: f64, expected: f64, relative: f64, absolute: f64) -> bool {
+    (declared - expected).abs() > (expected.abs() * relative).max(absolute)
+}
+
+fn sugars_exceed_carbs(b: &mut Breakdown, repair: bool) -> Option<CheckValues> {
+    let (sugars, carbohydrates) = (b.get("sugars")?, b.get("carbohydrates")?);
+    if sugars <= carbohydrates + 0.1 {
+        return None;
+    }
+    if repair {
+        b.set("sugars", Some(carbohydrates));
+    }
+    Some(BTreeMap::from([("sugars", sugars), ("carbohydrates", carbohydrates)]))
+}
+
+fn saturated_exceeds_fat(b: &mut Breakdown, repair: bool) -> Option<CheckValues> {
+    let (saturated, fat) = (b.get("saturated")?, b.get("fat")?);
+    if saturated <= fat + 0.1 {
+        return None;
+    }
+    if repair {
+        b.set("saturated", Some(fat));
+    }
+    Some(BTreeMap::from([("saturated", saturated), ("fat", fat)]))
+}
+
+fn macro_sum_over_100(b: &mut Breakdown, _repair: bool) -> Option<CheckValues> {
+    // Fiber is left out: US labels count it inside carbohydrates, so adding it would count it twice
+    let parts = ["fat", "carbohydrates", "proteins", "salt"];
+    let values: CheckValues = parts.iter().filter_map(|&name| Some((name, b.get(name)?))).collect();
+    let sum: f64 = values.values().sum();
+    // Rounding on labels can push a pure product slightly over 100g
+    (sum > 101.0).then_some(values)
+}
+
+fn energy_atwater_mismatch(b: &mut Breakdown, _repair: bool) -> Option<CheckValues> {
+    let energy_kcal = b.get("energy_kcal")?;
+    let (carbohydrates, fat, proteins) = (b.get("carbohydrates")?, b.get("fat")?, b.get("proteins")?);
+    let fiber = b.get("fiber").unwrap_or(0.0);
+
+    let estimate = carbohydrates * KCAL_PER_G_CARBOHYDRATE
+        + proteins * KCAL_PER_G_PROTEIN
+        + fat * KCAL_PER_G_FAT
+        + fiber * KCAL_PER_G_FIBER;
+
+    // Polyols, alcohol and organic acids aren't in the estimate, so the tolerance is generous
+    disagrees(energy_kcal, estimate, 0.25, 25.0)
+        .then(|| BTreeMap::from([("energy_kcal", energy_kcal), ("atwater_estimate_kcal", estimate)]))
+}
+
+fn energy_kj_mismatch(b: &mut Breakdown, repair: bool) -> Option<CheckValues> {
+    let (energy_kcal, energy_kj) = (b.get("energy_kcal")?, b.get("energy_kj")?);
+    if !disagrees(energy_kj * KCAL_PER_KJ, energy_kcal, 0.05, 2.0) {
+        return None;
+    }
+    // kcal is the value records are validated on, so kJ is derived from it
+    if repair {
+        b.set("energy_kj", Some(energy_kcal / KCAL_PER_KJ));
+    }
+    Some(BTreeMap::from([("energy_kcal", energy_kcal), ("energy_kj", energy_kj)]))
+}
+
+fn salt_sodium_mismatch(b: &mut Breakdown, repair: bool) -> Option<CheckValues> {
+    let (salt, sodium) = (b.get("salt")?, b.get("sodium")?);
+    if !disagrees(sodium * SALT_PER_G_SODIUM, salt, 0.1, 0.02) {
+        return None;
+    }
+    // Salt is what EU labels declare, so sodium is derived from it
+    if repair {
+        b.set("sodium", Some(salt / SALT_PER_G_SODIUM));
+    }
+    Some(BTreeMap::from([("salt", salt), ("sodium", sodium)]))
+}
+
+#[derive(Debug, Default, Serialize)]
+struct CheckCounts {
+    flagged: usize,
+    repaired: usize,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct CountrySummary {
+    products: usize,
+    products_with_issues: usize,
+    checks: BTreeMap<QualityCheck, CheckCounts>,
+}
+
+/// Per-country tally of the issues found on published products.
+#[derive(Default)]
+pub struct QualitySummary {
+    countries: HashMap<String, CountrySummary>,
+}
+
+impl QualitySummary {
+    pub fn add(&mut self, country_code: &str, issues: &[QualityIssue]) {
+        let summary = self.countries.entry(country_code.to_string()).or_default();
+        summary.products += 1;
+        if !issues.is_empty() {
+            summary.products_with_issues += 1;
+        }
+        for issue in issues {
+            let counts = summary.checks.entry(issue.check).or_default();
+            match issue.action {
+                IssueAction::Flagged => counts.flagged += 1,
+                IssueAction::Repaired => counts.repaired += 1,
+            }
+        }
+    }
+
+    /// Writes `{base_dir}/{country}/quality.json` for every country seen.
+    pub fn write(&self, base_dir: &Path) -> Result<()> {
+        for (country_code, summary) in &self.countries {
+            let country_dir = base_dir.join(country_code);
+            ensure_dir(&country_dir)?;
+            let path = country_dir.join("quality.json");
+            let file = File::create(&path)
+                .with_context(|| format!("Failed to create quality summary: {:?}", path))?;
+            let mut writer = BufWriter::new(file);
+            serde_json::to_writer_pretty(&mut writer, summary)
+                .with_context(|| format!("Failed to write quality summary: {:?}", path))?;
+            writer.flush()
+                .with_context(|| format!("Failed to flush quality summary: {:?}", path))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::nutrients::registry;
+
+    fn breakdown(values: &[(&str, f64)]) -> Breakdown {
+        let mut breakdown = Breakdown::new(vec![None; registry().defs().len()], BTreeMap::new());
+        for &(name, value) in values {
+            breakdown.set(name, Some(value));
+        }
+        breakdown
+    }
+
+    /// Runs a single check under `policy`, every other check ignored.
+    fn run(check: QualityCheck, policy: Policy, b: &mut Breakdown) -> Result<Vec<QualityIssue>, QualityIssue> {
+        validate(b, &QualityPolicy::new(Policy::Ignore, &[(check, policy)]))
+    }
+
+    fn failed(check: QualityCheck, values: &[(&str, f64)]) -> bool {
+        !run(check, Policy::Flag, &mut breakdown(values)).unwrap().is_empty()
+    }
+
+    #[test]
+    fn sugars_exceed_carbs() {
+        let check = QualityCheck::SugarsExceedCarbs;
+        assert!(failed(check, &[("sugars", 12.0), ("carbohydrates", 10.0)]));
+        assert!(!failed(check, &[("sugars", 10.05), ("carbohydrates", 10.0)]));
+        assert!(!failed(check, &[("sugars", 12.0)]));
+    }
+
+    #[test]
+    fn saturated_exceeds_fat() {
+        let check = QualityCheck::SaturatedExceedsFat;
+        assert!(failed(check, &[("saturated", 5.0), ("fat", 3.0)]));
+        assert!(!failed(check, &[("saturated", 3.0), ("fat", 3.0)]));
+    }
+
+    #[test]
+    fn macro_sum_over_100_leaves_fiber_out() {
+        let check = QualityCheck::MacroSumOver100;
+        assert!(failed(check, &[("fat", 50.0), ("carbohydrates", 40.0), ("proteins", 10.0), ("salt", 2.0)]));
+        assert!(!failed(check, &[("fat", 50.0), ("carbohydrates", 40.0), ("proteins", 10.5), ("salt", 0.5)]));
+        // Chia seeds: 42g carbohydrates of which 34g fiber
+        assert!(!failed(check, &[("fat", 31.0), ("carbohydrates", 42.0), ("fiber", 34.0), ("proteins", 17.0)]));
+    }
+
+    #[test]
+    fn energy_atwater_mismatch() {
+        let check = QualityCheck::EnergyAtwaterMismatch;
+        let macros = [("carbohydrates", 50.0), ("fat", 10.0), ("proteins", 10.0)];
+        // 50×4 + 10×9 + 10×4 = 330 kcal
+        assert!(!failed(check, &[macros.as_slice(), &[("energy_kcal", 350.0)]].concat()));
+        assert!(failed(check, &[macros.as_slice(), &[("energy_kcal", 500.0)]].concat()));
+        // The absolute floor keeps small values from tripping the relative tolerance
+        assert!(!failed(check, &[("carbohydrates", 1.0), ("fat", 0.0), ("proteins", 0.0), ("energy_kcal", 20.0)]));
+    }
+
+    #[test]
+    fn energy_kj_mismatch() {
+        let check = QualityCheck::EnergyKjMismatch;
+        assert!(!failed(check, &[("energy_kcal", 100.0), ("energy_kj", 418.0)]));
+        assert!(failed(check, &[("energy_kcal", 100.0), ("energy_kj", 100.0)]));
+    }
+
+    #[test]
+    fn salt_sodium_mismatch() {
+        let check = QualityCheck::SaltSodiumMismatch;
+        assert!(!failed(check, &[("salt", 1.0), ("sodium", 0.4)]));
+        assert!(failed(check, &[("salt", 1.0), ("sodium", 1.0)]));
+        assert!(!failed(check, &[("salt", 0.01), ("sodium", 0.0)]));
+    }
+
+    #[test]
+    fn ignore_skips_and_flag_reports_without_touching_values() {
+        let values = [("sugars", 12.0), ("carbohydrates", 10.0)];
+        let check = QualityCheck::SugarsExceedCarbs;
+
+        assert!(run(check, Policy::Ignore, &mut breakdown(&values)).unwrap().is_empty());
+
+        let mut b = breakdown(&values);
+        let issues = run(check, Policy::Flag, &mut b).unwrap();
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].action, IssueAction::Flagged);
+        assert_eq!(issues[0].values, BTreeMap::from([("sugars", 12.0), ("carbohydrates", 10.0)]));
+        assert_eq!(b.get("sugars"), Some(12.0));
+    }
+
+    #[test]
+    fn repair_fixes_values_where_a_repair_exists() {
+        let mut b = breakdown(&[("sugars", 12.0), ("carbohydrates", 10.0), ("saturated", 5.0), ("fat", 3.0)]);
+        let policy = QualityPolicy::new(Policy::Repair, &[]);
+        let issues = validate(&mut b, &policy).unwrap();
+        assert!(issues.iter().all(|issue| issue.action == IssueAction::Repaired));
+        assert_eq!((b.get("sugars"), b.get("saturated")), (Some(10.0), Some(3.0)));
+        // The issue keeps the values from before the repair
+        assert_eq!(issues[0].values["sugars"], 12.0);
+
+        let mut b = breakdown(&[("energy_kcal", 100.0), ("energy_kj", 100.0), ("salt", 1.0), ("sodium", 1.0)]);
+        run(QualityCheck::EnergyKjMismatch, Policy::Repair, &mut b).unwrap();
+        run(QualityCheck::SaltSodiumMismatch, Policy::Repair, &mut b).unwrap();
+        assert!((b.get("energy_kj").unwrap() - 418.41).abs() < 0.01);
+        assert_eq!(b.get("sodium"), Some(0.4));
+    }
+
+    #[test]
+    fn repair_falls_back_to_flag_without_a_repair() {
+        let mut b = breakdown(&[("fat", 60.0), ("carbohydrates", 60.0)]);
+        let issues = run(QualityCheck::MacroSumOver100, Policy::Repair, &mut b).unwrap();
+        assert_eq!(issues[0].action, IssueAction::Flagged);
+        assert_eq!(b.get("fat"), Some(60.0));
+    }
+
+    #[test]
+    fn reject_returns_the_failed_check() {
+        let mut b = breakdown(&[("fat", 60.0), ("carbohydrates", 60.0), ("saturated", 70.0)]);
+        let policy = QualityPolicy::new(Policy::Flag, &[(QualityCheck::MacroSumOver100, Policy::Reject)]);
+        let rejected = validate(&mut b, &policy).unwrap_err();
+        assert_eq!(rejected.check, QualityCheck::MacroSumOver100);
+    }
+
+    #[test]
+    fn parses_policy_overrides() {
+        assert_eq!(
+            parse_policy_override("macro_sum_over_100 = reject").unwrap(),
+            (QualityCheck::MacroSumOver100, Policy::Reject)
+        );
+        assert_eq!(QualityCheck::SugarsExceedCarbs.name(), "sugars_exceed_carbs");
+        assert!(parse_policy_override("sugars_exceed_carbs").is_err());
+        assert!(parse_policy_override("sugars=flag").is_err());
+    }
+}
@@ -0,0 +1,152 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RejectReason {
+    /// The CSV reader couldn't parse the row
+    MalformedRow,
+    /// No digits in the `code` column
+    EmptyCode,
+    /// `energy-kcal_100g` is empty or not a number
+    MissingEnergy,
+    /// None of carbohydrates, fat or proteins is set
+    NoMacros,
+    /// A data quality check with a `reject` policy failed
+    QualityCheck,
+    /// The record parsed but its product file couldn't be written
+    ProcessingError,
+}
+
+impl RejectReason {
+    fn label(self) -> &'static str {
+        match self {
+            RejectReason::MalformedRow => "malformed row",
+            RejectReason::EmptyCode => "empty code",
+            RejectReason::MissingEnergy => "missing energy",
+            RejectReason::NoMacros => "no macros",
+            RejectReason::QualityCheck => "quality check",
+            RejectReason::ProcessingError => "processing error",
+        }
+    }
+}
+
+/// One line of `rejects.jsonl`.
+#[derive(Debug, Serialize)]
+pub struct Rejection {
+    /// Line in the CSV file (the header is line 1), when known
+    pub row: Option<u64>,
+    pub code: Option<String>,
+    pub reason: RejectReason,
+    /// The raw column values that caused the rejection
+    pub values: BTreeMap<String, String>,
+}
+
+impl Rejection {
+    pub fn new(row: Option<u64>, code: Option<&str>, reason: RejectReason) -> Self {
+        Self {
+            row,
+            code: code.filter(|c| !c.is_empty()).map(str::to_string),
+            reason,
+            values: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_value(mut self, column: &str, value: Option<&str>) -> Self {
+        self.values.insert(column.to_string(), value.unwrap_or("").to_string());
+        self
+    }
+}
+
+/// Streams rejections to a JSONL file and tallies them per reason.
+pub struct RejectLog {
+    writer: BufWriter<File>,
+    tally: HashMap<RejectReason, usize>,
+}
+
+impl RejectLog {
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = File::create(path)
+            .with_context(|| format!("Failed to create rejects file: {:?}", path))?;
+        Ok(Self {
+            writer: BufWriter::with_capacity(64 * 1024, file),
+            tally: HashMap::new(),
+        })
+    }
+
+    pub fn record(&mut self, rejection: &Rejection) -> Result<()> {
+        *self.tally.entry(rejection.reason).or_insert(0) += 1;
+        let line = serde_json::to_string(rejection)
+            .with_context(|| "Failed to serialize rejection")?;
+        writeln!(self.writer, "{}", line)
+            .with_context(|| "Failed to write rejection")?;
+        Ok(())
+    }
+
+    pub fn total(&self) -> usize {
+        self.tally.values().sum()
+    }
+
+    /// Flushes the log and prints the per-reason tally, most common first.
+    pub fn finish(mut self) -> Result<()> {
+        self.writer.flush()
+            .with_context(|| "Failed to flush rejects file")?;
+
+        for (reason, count) in self.sorted_tally() {
+            println!("      {}: {}", reason.label(), count);
+        }
+        Ok(())
+    }
+
+    /// Rejections per reason, most common first.
+    fn sorted_tally(&self) -> Vec<(RejectReason, usize)> {
+        let mut tally: Vec<(RejectReason, usize)> = self.tally.iter().map(|(reason, count)| (*reason, *count)).collect();
+        tally.sort_by(|(a_reason, a_count), (b_reason, b_count)| b_count.cmp(a_count).then(a_reason.cmp(b_reason)));
+        tally
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+    use std::fs;
+
+    #[test]
+    fn writes_one_line_per_rejection_and_tallies_reasons() {
+        let path = std::env::temp_dir().join(format!("rejects-{}.jsonl", std::process::id()));
+        let mut log = RejectLog::create(&path).unwrap();
+        log.record(&Rejection::new(Some(6), Some("5000000000001"), RejectReason::MissingEnergy)
+            .with_value("energy-kcal_100g", Some("")))
+            .unwrap();
+        log.record(&Rejection::new(Some(7), Some(""), RejectReason::EmptyCode).with_value("code", None)).unwrap();
+        log.record(&Rejection::new(None, None, RejectReason::MalformedRow)).unwrap();
+        log.record(&Rejection::new(Some(9), Some("42"), RejectReason::MissingEnergy)).unwrap();
+
+        assert_eq!(log.total(), 4);
+        // Most common first, ties in declaration order
+        assert_eq!(
+            log.sorted_tally(),
+            [(RejectReason::MissingEnergy, 2), (RejectReason::MalformedRow, 1), (RejectReason::EmptyCode, 1)]
+        );
+        log.finish().unwrap();
+
+        let lines: Vec<Value> = fs::read_to_string(&path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines, [
+            json!({"row": 6, "code": "5000000000001", "reason": "missing_energy", "values": {"energy-kcal_100g": ""}}),
+            json!({"row": 7, "code": null, "reason": "empty_code", "values": {"code": ""}}),
+            json!({"row": null, "code": null, "reason": "malformed_row", "values": {}}),
+            json!({"row": 9, "code": "42", "reason": "missing_energy", "values": {}}),
+        ]);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,463 @@
+use anyhow::{Context, Result};
+use brotli::Decompressor;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tracing::{info, warn};
+
+use crate::text;
+
+// ---- Config ----
+const CATALOGS_SUBDIR: &str = "indexes/catalogs";
+const MANIFEST_FILE: &str = "manifest.json";
+const RELOAD_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The first line of every catalog chunk.
+#[derive(Deserialize)]
+struct Header {
+    columns: Vec<HeaderColumn>,
+}
+
+#[derive(Deserialize)]
+struct HeaderColumn {
+    name: String,
+}
+
+struct Entry {
+    name_tokens: Vec<String>,
+    brand_tokens: Vec<String>,
+    code: String,
+    // Column names of the chunk the row came from, shared by all of its rows
+    columns: Arc<[String]>,
+    // Values in `columns` order
+    row: Vec<Value>,
+}
+
+impl Entry {
+    fn code(&self) -> &str {
+        &self.code
+    }
+
+    fn to_object(&self) -> Value {
+        let object: Map<String, Value> = self.columns.iter().cloned().zip(self.row.iter().cloned()).collect();
+        Value::Object(object)
+    }
+}
+
+#[derive(Deserialize)]
+struct ChunkRef {
+    url: String,
+}
+
+/// The parts of a country's `manifest.json` the server needs.
+#[derive(Deserialize)]
+struct Manifest {
+    chunks: Vec<ChunkRef>,
+}
+
+/// Token index over a single country catalog.
+struct CountryIndex {
+    entries: Vec<Entry>,
+    // Sorted so a prefix lookup is a range scan
+    tokens: BTreeMap<String, Vec<u32>>,
+}
+
+impl CountryIndex {
+    /// Loads every chunk listed in a country's manifest, in manifest order.
+    fn load(manifest_path: &Path) -> Result<Self> {
+        let manifest_file = File::open(manifest_path)
+            .with_context(|| format!("Failed to open catalog manifest: {:?}", manifest_path))?;
+        let manifest: Manifest = serde_json::from_reader(BufReader::new(manifest_file))
+            .with_context(|| format!("Failed to parse catalog manifest: {:?}", manifest_path))?;
+        let catalog_dir = manifest_path.parent().unwrap_or(Path::new("."));
+
+        let mut index = Self {
+            entries: Vec::new(),
+            tokens: BTreeMap::new(),
+        };
+        for chunk in &manifest.chunks {
+            index.load_chunk(&catalog_dir.join(&chunk.url))?;
+        }
+        Ok(index)
+    }
+
+    fn load_chunk(&mut self, path: &Path) -> Result<()> {
+        let file = File::open(path).with_context(|| format!("Failed to open catalog chunk: {:?}", path))?;
+        let reader = BufReader::new(Decompressor::new(file, 64 * 1024));
+
+        let mut lines = reader.lines();
+        let header_line = lines
+            .next()
+            .transpose()
+            .with_context(|| format!("Failed to read catalog chunk: {:?}", path))?
+            .with_context(|| format!("Catalog chunk has no header: {:?}", path))?;
+        let header: Header = serde_json::from_str(&header_line)
+            .with_context(|| format!("Failed to parse catalog chunk header: {:?}", path))?;
+        let columns: Arc<[String]> = header.columns.into_iter().map(|column| column.name).collect();
+        let position = |name: &str| columns.iter().position(|column| column == name);
+        let (code_at, name_at, brand_at) = (position("code"), position("name"), position("brand"));
+
+        for line in lines {
+            let line = line.with_context(|| format!("Failed to read catalog chunk: {:?}", path))?;
+            // Rows are arrays in header column order or objects keyed by column name;
+            // both are kept as values in header order
+            let row: Vec<Value> = match serde_json::from_str::<Value>(&line) {
+                Ok(Value::Array(mut values)) => {
+                    values.resize(columns.len(), Value::Null);
+                    values
+                }
+                Ok(Value::Object(mut object)) => {
+                    columns.iter().map(|column| object.remove(column).unwrap_or(Value::Null)).collect()
+                }
+                _ => continue,
+            };
+
+            let field = |at: Option<usize>| at.and_then(|i| row[i].as_str());
+            let code = field(code_at).unwrap_or_default().to_string();
+            let name_tokens = field(name_at).map(text::tokenize).unwrap_or_default();
+            let brand_tokens = field(brand_at).map(text::tokenize).unwrap_or_default();
+
+            let id = self.entries.len() as u32;
+            let unique: HashSet<&String> = name_tokens.iter().chain(&brand_tokens).collect();
+            for token in unique {
+                self.tokens.entry(token.clone()).or_default().push(id);
+            }
+
+            self.entries.push(Entry {
+                name_tokens,
+                brand_tokens,
+                code,
+                columns: Arc::clone(&columns),
+                row,
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Entries where every query token prefixes some name or brand token.
+    fn matching(&self, query_tokens: &[String]) -> Vec<u32> {
+        let mut result: Option<HashSet<u32>> = None;
+
+        for query_token in query_tokens {
+            let mut ids = HashSet::new();
+            for (_, postings) in self
+                .tokens
+                .range(query_token.clone()..)
+                .take_while(|(token, _)| token.starts_with(query_token.as_str()))
+            {
+                ids.extend(postings.iter().copied());
+            }
+
+            result = Some(match result {
+                Some(previous) => previous.intersection(&ids).copied().collect(),
+                None => ids,
+            });
+            if result.as_ref().is_some_and(HashSet::is_empty) {
+                break;
+            }
+        }
+
+        result.map(|ids| ids.into_iter().collect()).unwrap_or_default()
+    }
+}
+
+/// Relevance of an entry: exact token hits beat prefix hits, names beat brands,
+/// and names that start with the query rank first.
+fn score(entry: &Entry, query_tokens: &[String]) -> u32 {
+    let mut score = 0;
+    for query_token in query_tokens {
+        let in_field = |tokens: &[String], exact: u32, prefix: u32| {
+            if tokens.iter().any(|t| t == query_token) {
+                exact
+            } else if tokens.iter().any(|t| t.starts_with(query_token.as_str())) {
+                prefix
+            } else {
+                0
+            }
+        };
+        score += in_field(&entry.name_tokens, 10, 6).max(in_field(&entry.brand_tokens, 5, 3));
+    }
+    if entry.name_tokens.first().zip(query_tokens.first()).is_some_and(|(n, q)| n.starts_with(q.as_str())) {
+        score += 4;
+    }
+    score
+}
+
+pub struct SearchIndex {
+    countries: HashMap<String, CountryIndex>,
+}
+
+pub struct SearchPage {
+    pub total: usize,
+    pub results: Vec<Value>,
+}
+
+impl SearchIndex {
+    fn load(catalogs_dir: &Path) -> Result<Self> {
+        let mut countries = HashMap::new();
+        for (country, path) in catalog_files(catalogs_dir)? {
+            match CountryIndex::load(&path) {
+                Ok(index) => {
+                    countries.insert(country, index);
+                }
+                Err(e) => warn!("❌ Skipping catalog {:?}: {}", path, e),
+            }
+        }
+        Ok(Self { countries })
+    }
+
+    pub fn has_country(&self, country: &str) -> bool {
+        self.countries.contains_key(country)
+    }
+
+    /// Searches one country, or all of them (deduplicated by code) when `country` is `None`.
+    pub fn search(&self, query: &str, country: Option<&str>, page: usize, page_size: usize) -> SearchPage {
+        let query_tokens = text::tokenize(query);
+        if query_tokens.is_empty() {
+            return SearchPage { total: 0, results: Vec::new() };
+        }
+
+        let indexes: Vec<&CountryIndex> = match country {
+            Some(country) => self.countries.get(country).into_iter().collect(),
+            None => self.countries.values().collect(),
+        };
+
+        let mut seen = HashSet::new();
+        let mut hits: Vec<(u32, &Entry)> = Vec::new();
+        for index in indexes {
+            for id in index.matching(&query_tokens) {
+                let entry = &index.entries[id as usize];
+                if seen.insert(entry.code()) {
+                    hits.push((score(entry, &query_tokens), entry));
+                }
+            }
+        }
+
+        // Highest score first; shorter names break ties, then code for a stable order
+        hits.sort_by(|(a_score, a), (b_score, b)| {
+            b_score
+                .cmp(a_score)
+                .then(a.name_tokens.len().cmp(&b.name_tokens.len()))
+                .then(a.code().cmp(b.code()))
+        });
+
+        let total = hits.len();
+        let results = hits
+            .iter()
+            .skip(page.saturating_mul(page_size))
+            .take(page_size)
+            .map(|(_, entry)| entry.to_object())
+            .collect();
+
+        SearchPage { total, results }
+    }
+}
+
+fn catalog_files(catalogs_dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let mut files = Vec::new();
+    if !catalogs_dir.is_dir() {
+        return Ok(files);
+    }
+    for dir_entry in fs::read_dir(catalogs_dir)
+        .with_context(|| format!("Failed to read catalogs directory: {:?}", catalogs_dir))?
+    {
+        let dir_entry = dir_entry?;
+        let path = dir_entry.path().join(MANIFEST_FILE);
+        if path.is_file() {
+            files.push((dir_entry.file_name().to_string_lossy().into_owned(), path));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Changes whenever a catalog is added, removed or rewritten; the processor
+/// writes each manifest after its chunks, so the manifest stands in for the catalog.
+fn catalogs_fingerprint(catalogs_dir: &Path) -> Vec<(String, u64, Option<SystemTime>)> {
+    catalog_files(catalogs_dir)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(country, path)| {
+            let metadata = fs::metadata(&path).ok();
+            let len = metadata.as_ref().map(|m| m.len()).unwrap_or(0);
+            let modified = metadata.and_then(|m| m.modified().ok());
+            (country, len, modified)
+        })
+        .collect()
+}
+
+/// The live search index, rebuilt in the background whenever the catalogs on disk change.
+#[derive(Clone)]
+pub struct SharedSearchIndex {
+    catalogs_dir: PathBuf,
+    current: Arc<RwLock<Option<Arc<SearchIndex>>>>,
+}
+
+impl SharedSearchIndex {
+    pub fn new(static_dir: &Path) -> Self {
+        Self {
+            catalogs_dir: static_dir.join(CATALOGS_SUBDIR),
+            current: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    /// Returns the loaded index, or `None` while the first load is still running.
+    pub fn get(&self) -> Option<Arc<SearchIndex>> {
+        self.current.read().unwrap().clone()
+    }
+
+    /// Loads the catalogs, then polls them and reloads after a dataset swap.
+    pub fn spawn_loader(&self) {
+        let shared = self.clone();
+        tokio::spawn(async move {
+            let mut loaded_fingerprint = None;
+            loop {
+                let catalogs_dir = shared.catalogs_dir.clone();
+                let fingerprint = tokio::task::spawn_blocking(move || catalogs_fingerprint(&catalogs_dir))
+                    .await
+                    .unwrap_or_default();
+
+                if loaded_fingerprint.as_ref() != Some(&fingerprint) {
+                    let catalogs_dir = shared.catalogs_dir.clone();
+                    let start_time = Instant::now();
+                    match tokio::task::spawn_blocking(move || SearchIndex::load(&catalogs_dir)).await {
+                        Ok(Ok(index)) => {
+                            info!(
+                                "🔎 Search index loaded: {} countries in {:.2}s",
+                                index.countries.len(),
+                                start_time.elapsed().as_secs_f64()
+                            );
+                            *shared.current.write().unwrap() = Some(Arc::new(index));
+                            loaded_fingerprint = Some(fingerprint);
+                        }
+                        Ok(Err(e)) => warn!("❌ Failed to load search index: {}", e),
+                        Err(e) => warn!("❌ Search index loader panicked: {}", e),
+                    }
+                }
+
+                tokio::time::sleep(RELOAD_CHECK_INTERVAL).await;
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use brotli::CompressorWriter;
+    use std::io::Write;
+
+    const HEADER: &str = r#"{"schema_version":1,"row_format":"array","columns":[{"name":"code"},{"name":"name"},{"name":"brand"}]}"#;
+
+    /// A fresh catalogs directory under the system temp dir.
+    fn catalogs_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("search-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes one country catalog, one brotli chunk per slice of rows.
+    fn write_catalog(catalogs_dir: &Path, country: &str, chunks: &[&[&str]]) {
+        let dir = catalogs_dir.join(country);
+        fs::create_dir_all(&dir).unwrap();
+        let mut refs = Vec::new();
+        for (i, rows) in chunks.iter().enumerate() {
+            let url = format!("catalog-{:04}.jsonl.br", i + 1);
+            let mut writer = CompressorWriter::new(File::create(dir.join(&url)).unwrap(), 4096, 5, 22);
+            writeln!(writer, "{}", HEADER).unwrap();
+            for row in *rows {
+                writeln!(writer, "{}", row).unwrap();
+            }
+            refs.push(serde_json::json!({ "url": url }));
+        }
+        let manifest = serde_json::json!({ "country": country, "chunks": refs });
+        fs::write(dir.join(MANIFEST_FILE), manifest.to_string()).unwrap();
+    }
+
+    fn codes(page: &SearchPage) -> Vec<&str> {
+        page.results.iter().map(|row| row["code"].as_str().unwrap()).collect()
+    }
+
+    fn sample_index(test: &str) -> SearchIndex {
+        let dir = catalogs_dir(test);
+        write_catalog(
+            &dir,
+            "fr",
+            &[
+                &[
+                    r#"["1", "Nutella pâte à tartiner", "Ferrero"]"#,
+                    r#"["2", "Pâte feuilletée", "Herta"]"#,
+                ],
+                &[
+                    r#"{"code": "3", "name": "Crème de marrons", "brand": "Nutri Bio"}"#,
+                    r#"["4", "Biscuits nutella", "Ferrero"]"#,
+                ],
+            ],
+        );
+        write_catalog(&dir, "it", &[&[r#"["1", "Nutella pâte à tartiner", "Ferrero"]"#, r#"["5", "Nutella", "Ferrero"]"#]]);
+        SearchIndex::load(&dir).unwrap()
+    }
+
+    #[test]
+    fn every_query_token_must_prefix_a_name_or_brand_token() {
+        let index = sample_index("prefix");
+        assert_eq!(codes(&index.search("pate", Some("fr"), 0, 10)), ["2", "1"]);
+        assert_eq!(codes(&index.search("PÂTE tart", Some("fr"), 0, 10)), ["1"]);
+        assert_eq!(codes(&index.search("creme nutri", Some("fr"), 0, 10)), ["3"]);
+        assert!(index.search("pate marrons", Some("fr"), 0, 10).results.is_empty());
+        assert!(index.search("  ", None, 0, 10).results.is_empty());
+    }
+
+    #[test]
+    fn ranks_exact_names_over_prefixes_and_brands() {
+        let index = sample_index("ranking");
+        // "Nutella" exact and leading in 1, exact but not leading in 4, only a brand prefix in 3
+        assert_eq!(codes(&index.search("nutella", Some("fr"), 0, 10)), ["1", "4"]);
+        assert_eq!(codes(&index.search("nut", Some("fr"), 0, 10)), ["1", "4", "3"]);
+        // Across countries codes are deduplicated, and the shorter name wins a tie
+        assert_eq!(codes(&index.search("nutella", None, 0, 10)), ["5", "1", "4"]);
+    }
+
+    #[test]
+    fn pages_through_sorted_hits() {
+        let index = sample_index("pagination");
+        let first = index.search("nut", None, 0, 2);
+        let second = index.search("nut", None, 1, 2);
+        assert_eq!((first.total, second.total), (4, 4));
+        assert_eq!(codes(&first), ["5", "1"]);
+        assert_eq!(codes(&second), ["4", "3"]);
+        assert!(index.search("nut", None, 2, 2).results.is_empty());
+    }
+
+    #[test]
+    fn rows_come_back_as_objects_and_unknown_countries_are_empty() {
+        let index = sample_index("rows");
+        let page = index.search("marrons", Some("fr"), 0, 10);
+        assert_eq!(page.results[0], serde_json::json!({"code": "3", "name": "Crème de marrons", "brand": "Nutri Bio"}));
+        assert!(index.has_country("it") && !index.has_country("de"));
+        assert_eq!(index.search("nutella", Some("de"), 0, 10).total, 0);
+    }
+
+    #[test]
+    fn fingerprint_changes_when_a_catalog_is_added_or_rewritten() {
+        let dir = catalogs_dir("fingerprint");
+        let empty = catalogs_fingerprint(&dir);
+        assert!(empty.is_empty());
+
+        write_catalog(&dir, "fr", &[&[r#"["1", "Nutella", "Ferrero"]"#]]);
+        let loaded = catalogs_fingerprint(&dir);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(catalogs_fingerprint(&dir), loaded);
+
+        write_catalog(&dir, "fr", &[&[r#"["1", "Nutella", "Ferrero"]"#], &[r#"["2", "Pâte", "Herta"]"#]]);
+        assert_ne!(catalogs_fingerprint(&dir), loaded);
+        assert_eq!(SearchIndex::load(&dir).unwrap().search("pate", None, 0, 10).total, 1);
+    }
+}
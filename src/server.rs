@@ -1,11 +1,13 @@
 use anyhow::{Context, Result};
-use http_body_util::Full;
+use http_body_util::combinators::BoxBody;
+use http_body_util::{BodyExt, Full};
 use hyper::body::Bytes;
 use hyper::service::service_fn;
 use hyper::{Method, Request, Response, StatusCode, header};
 use hyper_util::rt::TokioExecutor;
 use hyper_util::server::conn::auto::Builder;
 use std::collections::HashMap;
+use std::convert::Infallible;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
@@ -16,15 +18,28 @@ use rustls::{Certificate, PrivateKey, ServerConfig};
 use rcgen::{Certificate as RcgenCert, CertificateParams, KeyPair, PKCS_ECDSA_P256_SHA256};
 use time::{OffsetDateTime, Duration};
 
+mod barcode;
+mod product_api;
+mod projection;
+mod search;
+mod text;
+
+const PRODUCTS_SUBDIR: &str = "products";
+
+/// Response body type: most routes answer with a `Full` body, the NDJSON batch lookup streams.
+type ResponseBody = BoxBody<Bytes, Infallible>;
+
 #[derive(Clone)]
 struct ServerState {
     static_dir: PathBuf,
     bandwidth_stats: Arc<std::sync::Mutex<HashMap<String, (u64, u64)>>>, // (bytes, requests)
+    search: search::SharedSearchIndex,
 }
 
 impl ServerState {
     fn new(static_dir: PathBuf) -> Self {
         Self {
+            search: search::SharedSearchIndex::new(&static_dir),
             static_dir,
             bandwidth_stats: Arc::new(std::sync::Mutex::new(HashMap::new())),
         }
@@ -67,7 +82,7 @@ impl ServerState {
         }
         
         // Default to JSON for other files
-        return "application/json";
+        "application/json"
     }
 
     fn get_file_path(&self, request_path: &str) -> PathBuf {
@@ -93,26 +108,103 @@ impl ServerState {
             }
         }
 
-        return "utf-8";
+        "utf-8"
+    }
+
+    /// Returns the first candidate code that has a product file, with its path.
+    fn find_product(&self, candidates: &[String]) -> Option<(String, PathBuf)> {
+        let products_dir = self.static_dir.join(PRODUCTS_SUBDIR);
+        candidates.iter().find_map(|code| {
+            let path = products_dir.join(format!("{}.json", code));
+            path.is_file().then(|| (code.clone(), path))
+        })
+    }
+}
+
+fn json_response(status: StatusCode, body: impl Into<Bytes>) -> Response<Full<Bytes>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(header::ACCESS_CONTROL_ALLOW_ORIGIN, "*")
+        .body(Full::new(body.into()))
+        .unwrap()
+}
+
+/// Splits a query string into decoded key/value pairs.
+fn query_params(query: Option<&str>) -> HashMap<String, String> {
+    query
+        .unwrap_or("")
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_decode(input: &str) -> String {
+    let bytes = input.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[i + 1..i + 3]).ok();
+                match hex.and_then(|h| u8::from_str_radix(h, 16).ok()) {
+                    Some(byte) => {
+                        out.push(byte);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            byte => out.push(byte),
+        }
+        i += 1;
     }
+    String::from_utf8_lossy(&out).into_owned()
 }
 
 async fn handle_request(
     state: ServerState,
     req: Request<hyper::body::Incoming>,
+) -> Result<Response<ResponseBody>, hyper::Error> {
+    info!("🌐 {} {}", req.method(), req.uri().path());
+
+    // The batch lookup is the only route that takes a request body
+    if req.uri().path() == "/api/v1/products:batch" && req.method() != Method::OPTIONS {
+        if req.method() != Method::POST {
+            return Ok(json_response(StatusCode::METHOD_NOT_ALLOWED, r#"{"error": "Method not allowed"}"#).map(BodyExt::boxed));
+        }
+        let params = query_params(req.uri().query());
+        let wants_ndjson = params.get("format").is_some_and(|f| f == "ndjson")
+            || req.headers()
+                .get(header::ACCEPT)
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|v| v.contains("application/x-ndjson"));
+        return Ok(product_api::handle_batch_lookup(&state, req.into_body(), &params, wants_ndjson).await);
+    }
+
+    let response = handle_get_request(state, req).await?;
+    Ok(response.map(BodyExt::boxed))
+}
+
+async fn handle_get_request(
+    state: ServerState,
+    req: Request<hyper::body::Incoming>,
 ) -> Result<Response<Full<Bytes>>, hyper::Error> {
     let start_time = Instant::now();
     let method = req.method().clone();
     let uri = req.uri().clone();
     let path = uri.path();
 
-    info!("🌐 {} {}", method, path);
-
     if method == Method::OPTIONS {
         return Ok(Response::builder()
             .status(StatusCode::OK)
             .header("access-control-allow-origin", "*")
-            .header("access-control-allow-methods", "GET, OPTIONS")
+            .header("access-control-allow-methods", "GET, POST, OPTIONS")
             .header("access-control-allow-headers", "*")
             .body(Full::new(Bytes::from("")))
             .unwrap());
@@ -129,15 +221,25 @@ async fn handle_request(
     }
 
     // Handle root path
-    if path == "/" || path == "" {
+    if path == "/" || path.is_empty() {
         return Ok(Response::builder()
             .status(StatusCode::OK)
             .header("content-type", "application/json")
             .header("access-control-allow-origin", "*")
-            .body(Full::new(Bytes::from(r#"{"message": "OpenFoodFacts Static Server", "endpoints": ["/static/*"]}"#)))
+            .body(Full::new(Bytes::from(r#"{"message": "OpenFoodFacts Static Server", "endpoints": ["/static/*", "/api/v1/products/{barcode}", "POST /api/v1/products:batch", "/api/v1/search"]}"#)))
             .unwrap());
     }
 
+    if path == "/api/v1/search" {
+        let params = query_params(uri.query());
+        return Ok(product_api::handle_search(&state, &params));
+    }
+
+    if let Some(barcode) = path.strip_prefix("/api/v1/products/") {
+        let params = query_params(uri.query());
+        return Ok(product_api::handle_product_lookup(&state, barcode, &params));
+    }
+
     let file_path = state.get_file_path(path);
     let content_type = state.get_content_type(&file_path);
     let content_encoding = state.get_content_encoding(&file_path);
@@ -243,6 +345,7 @@ fn load_tls_config() -> Result<Arc<ServerConfig>> {
 
 async fn run_server(static_dir: PathBuf) -> Result<()> {
     let state = ServerState::new(static_dir);
+    state.search.spawn_loader();
     
     let addr = "[::]:8443"; // HTTPS default port
     let listener = TcpListener::bind(addr).await
@@ -289,7 +392,7 @@ async fn main() -> Result<()> {
     tracing_subscriber::fmt::init();
     
     let args: Vec<String> = std::env::args().collect();
-    let static_dir = args.get(1).map(|s| PathBuf::from(s)).unwrap_or_else(|| PathBuf::from("static"));
+    let static_dir = args.get(1).map(PathBuf::from).unwrap_or_else(|| PathBuf::from("static"));
     
     // Ensure static directory exists
     if !static_dir.exists() {
@@ -0,0 +1,296 @@
+use regex::{Captures, Regex};
+use serde::Serialize;
+use std::sync::OnceLock;
+
+const GRAMS_PER_OZ: f64 = 28.3495;
+const GRAMS_PER_LB: f64 = 453.592;
+const ML_PER_FL_OZ: f64 = 29.5735;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MetricUnit {
+    G,
+    Ml,
+}
+
+impl MetricUnit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MetricUnit::G => "g",
+            MetricUnit::Ml => "ml",
+        }
+    }
+}
+
+/// A parsed `serving_size` string such as "1 cup (240 ml)".
+#[derive(Debug, Default, PartialEq)]
+pub struct Serving {
+    /// Household measure, e.g. "1 cup" or "2 biscuits"
+    pub description: Option<String>,
+    /// Number of household units, e.g. 2 for "2 biscuits"
+    pub count: Option<f64>,
+    /// Metric equivalent, converted from imperial units where needed
+    pub quantity: Option<f64>,
+    pub unit: Option<MetricUnit>,
+}
+
+// ---- Cached Regex ----
+fn measure_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(concat!(
+            r"(?i)(?P<num>\d+\s+\d+/\d+|\d+/\d+|\d+(?:[.,]\d+)?|[½¼¾])\s*",
+            r"(?P<unit>fl\.?\s*oz|fluid\s+ounces?|ounces?|oz|lbs?|pounds?",
+            r"|kg|kilograms?|mg|milligrams?|g|gr|grams?|grammes?",
+            r"|ml|millilit(?:er|re)s?|cl|dl|l|lit(?:er|re)s?)\b",
+        ))
+        .unwrap()
+    })
+}
+
+fn leading_number_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"^(\d+\s+\d+/\d+|\d+/\d+|\d+(?:[.,]\d+)?|[½¼¾])").unwrap())
+}
+
+struct Measure {
+    start: usize,
+    end: usize,
+    in_parens: bool,
+    quantity: f64,
+    unit: MetricUnit,
+    metric: bool,
+}
+
+/// Parses a free-text OFF `serving_size` value.
+pub fn parse(raw: &str) -> Serving {
+    let text = raw.trim();
+    if text.is_empty() {
+        return Serving::default();
+    }
+
+    let measures: Vec<Measure> = measure_regex()
+        .captures_iter(text)
+        .filter_map(|captures| to_measure(text, &captures))
+        .collect();
+
+    // A metric amount in parentheses is the label's own conversion, so it wins
+    let chosen = measures
+        .iter()
+        .find(|m| m.metric && m.in_parens)
+        .or_else(|| measures.iter().find(|m| m.metric))
+        .or_else(|| measures.first());
+
+    let description = household_description(text, &measures);
+    let count = description.as_deref().and_then(leading_number);
+
+    Serving {
+        description,
+        count,
+        quantity: chosen.map(|m| round_to(m.quantity, 2)),
+        unit: chosen.map(|m| m.unit),
+    }
+}
+
+fn to_measure(text: &str, captures: &Captures) -> Option<Measure> {
+    let whole = captures.get(0)?;
+    let amount = parse_number(captures.name("num")?.as_str())?;
+    let unit_token: String = captures
+        .name("unit")?
+        .as_str()
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .collect();
+
+    let (factor, unit, metric) = match unit_token.as_str() {
+        "floz" | "fluidounce" | "fluidounces" => (ML_PER_FL_OZ, MetricUnit::Ml, false),
+        "oz" | "ounce" | "ounces" => (GRAMS_PER_OZ, MetricUnit::G, false),
+        "lb" | "lbs" | "pound" | "pounds" => (GRAMS_PER_LB, MetricUnit::G, false),
+        "kg" | "kilogram" | "kilograms" => (1000.0, MetricUnit::G, true),
+        "mg" | "milligram" | "milligrams" => (0.001, MetricUnit::G, true),
+        "cl" => (10.0, MetricUnit::Ml, true),
+        "dl" => (100.0, MetricUnit::Ml, true),
+        "l" | "liter" | "liters" | "litre" | "litres" => (1000.0, MetricUnit::Ml, true),
+        "ml" => (1.0, MetricUnit::Ml, true),
+        s if s.starts_with("millilit") => (1.0, MetricUnit::Ml, true),
+        _ => (1.0, MetricUnit::G, true),
+    };
+
+    Some(Measure {
+        start: whole.start(),
+        end: whole.end(),
+        in_parens: paren_depth(&text[..whole.start()]) > 0,
+        quantity: amount * factor,
+        unit,
+        metric,
+    })
+}
+
+/// The first part of the text, outside parentheses first, that says more than a metric amount.
+fn household_description(text: &str, measures: &[Measure]) -> Option<String> {
+    // Blank out metric amounts so "1 serving = 30 g" leaves "1 serving"; imperial
+    // amounts stay, since "8 fl oz" is itself what the label calls the serving
+    let mut masked = text.to_string();
+    for measure in measures.iter().filter(|m| m.metric) {
+        masked.replace_range(measure.start..measure.end, &" ".repeat(measure.end - measure.start));
+    }
+
+    let mut outside = String::new();
+    let mut inside: Vec<String> = Vec::new();
+    let mut depth = 0;
+    for c in masked.chars() {
+        match c {
+            '(' | '[' => {
+                depth += 1;
+                if depth == 1 {
+                    inside.push(String::new());
+                }
+            }
+            ')' | ']' if depth > 0 => depth -= 1,
+            _ if depth > 0 => inside.last_mut().unwrap().push(c),
+            _ => outside.push(c),
+        }
+    }
+
+    std::iter::once(outside)
+        .chain(inside)
+        .map(|segment| clean_segment(&segment))
+        .find(|segment| segment.chars().any(char::is_alphabetic))
+}
+
+fn clean_segment(segment: &str) -> String {
+    let collapsed = segment.split_whitespace().collect::<Vec<_>>().join(" ");
+    collapsed
+        .trim_matches(|c: char| c.is_whitespace() || "=:,;/-~".contains(c))
+        .to_string()
+}
+
+fn leading_number(description: &str) -> Option<f64> {
+    leading_number_regex()
+        .captures(description)
+        .and_then(|c| c.get(1))
+        .and_then(|m| parse_number(m.as_str()))
+}
+
+fn parse_number(raw: &str) -> Option<f64> {
+    match raw {
+        "½" => return Some(0.5),
+        "¼" => return Some(0.25),
+        "¾" => return Some(0.75),
+        _ => {}
+    }
+
+    // Mixed fraction "1 1/2"
+    if let Some((whole, fraction)) = raw.split_once(char::is_whitespace) {
+        return Some(parse_number(whole.trim())? + parse_number(fraction.trim())?);
+    }
+
+    if let Some((numerator, denominator)) = raw.split_once('/') {
+        let numerator: f64 = numerator.parse().ok()?;
+        let denominator: f64 = denominator.parse().ok()?;
+        return (denominator != 0.0).then(|| numerator / denominator);
+    }
+
+    raw.replace(',', ".").parse().ok().filter(|n: &f64| n.is_finite())
+}
+
+fn paren_depth(prefix: &str) -> i32 {
+    prefix.chars().fold(0, |depth, c| match c {
+        '(' | '[' => depth + 1,
+        ')' | ']' => (depth - 1).max(0),
+        _ => depth,
+    })
+}
+
+fn round_to(value: f64, decimals: i32) -> f64 {
+    let scale = 10f64.powi(decimals);
+    (value * scale).round() / scale
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use MetricUnit::{Ml, G};
+
+    // (serving_size, description, count, quantity, unit)
+    type Case = (&'static str, Option<&'static str>, Option<f64>, Option<f64>, Option<MetricUnit>);
+
+    // Taken from real OFF rows
+    const CORPUS: &[Case] = &[
+        // Plain metric
+        ("30 g", None, None, Some(30.0), Some(G)),
+        ("30g", None, None, Some(30.0), Some(G)),
+        ("100 ml", None, None, Some(100.0), Some(Ml)),
+        ("250ml", None, None, Some(250.0), Some(Ml)),
+        ("12,5 g", None, None, Some(12.5), Some(G)),
+        ("37.5 g", None, None, Some(37.5), Some(G)),
+        ("50 grams", None, None, Some(50.0), Some(G)),
+        ("40 gr", None, None, Some(40.0), Some(G)),
+        ("125 grammes", None, None, Some(125.0), Some(G)),
+        ("200 millilitres", None, None, Some(200.0), Some(Ml)),
+        ("0.5 kg", None, None, Some(500.0), Some(G)),
+        ("500 mg", None, None, Some(0.5), Some(G)),
+        ("33 cl", None, None, Some(330.0), Some(Ml)),
+        ("2 dl", None, None, Some(200.0), Some(Ml)),
+        ("1 l", None, None, Some(1000.0), Some(Ml)),
+        ("1,5 L", None, None, Some(1500.0), Some(Ml)),
+        ("1 litre", None, None, Some(1000.0), Some(Ml)),
+        // Household measure with metric equivalent
+        ("1 cup (240 ml)", Some("1 cup"), Some(1.0), Some(240.0), Some(Ml)),
+        ("2 biscuits (25 g)", Some("2 biscuits"), Some(2.0), Some(25.0), Some(G)),
+        ("1 tbsp (15 ml)", Some("1 tbsp"), Some(1.0), Some(15.0), Some(Ml)),
+        ("3 pieces (30g)", Some("3 pieces"), Some(3.0), Some(30.0), Some(G)),
+        ("1/2 cup (120 ml)", Some("1/2 cup"), Some(0.5), Some(120.0), Some(Ml)),
+        ("1 1/2 cups (360 ml)", Some("1 1/2 cups"), Some(1.5), Some(360.0), Some(Ml)),
+        ("½ pizza (175 g)", Some("½ pizza"), Some(0.5), Some(175.0), Some(G)),
+        ("1 can (330 ml)", Some("1 can"), Some(1.0), Some(330.0), Some(Ml)),
+        ("1 Portion (125 g)", Some("1 Portion"), Some(1.0), Some(125.0), Some(G)),
+        ("1 bar [45 g]", Some("1 bar"), Some(1.0), Some(45.0), Some(G)),
+        ("1 serving = 30 g", Some("1 serving"), Some(1.0), Some(30.0), Some(G)),
+        ("1 portion: 40 g", Some("1 portion"), Some(1.0), Some(40.0), Some(G)),
+        // Metric first, household measure in parentheses
+        ("25g (2 biscuits)", Some("2 biscuits"), Some(2.0), Some(25.0), Some(G)),
+        ("30 g (1 cup)", Some("1 cup"), Some(1.0), Some(30.0), Some(G)),
+        ("125 g (1 pot)", Some("1 pot"), Some(1.0), Some(125.0), Some(G)),
+        // Imperial
+        ("8 fl oz", Some("8 fl oz"), Some(8.0), Some(236.59), Some(Ml)),
+        ("12 fl. oz", Some("12 fl. oz"), Some(12.0), Some(354.88), Some(Ml)),
+        ("1 oz", Some("1 oz"), Some(1.0), Some(28.35), Some(G)),
+        ("2 oz", Some("2 oz"), Some(2.0), Some(56.7), Some(G)),
+        ("1 lb", Some("1 lb"), Some(1.0), Some(453.59), Some(G)),
+        ("8 fl oz (240 ml)", Some("8 fl oz"), Some(8.0), Some(240.0), Some(Ml)),
+        ("1 oz (28 g)", Some("1 oz"), Some(1.0), Some(28.0), Some(G)),
+        ("1 cup (8 fl oz)", Some("1 cup"), Some(1.0), Some(236.59), Some(Ml)),
+        ("about 11 pieces (1 oz)", Some("about 11 pieces"), None, Some(28.35), Some(G)),
+        // No metric amount at all
+        ("1 portion", Some("1 portion"), Some(1.0), None, None),
+        ("2 slices", Some("2 slices"), Some(2.0), None, None),
+        ("portion", Some("portion"), None, None, None),
+        ("", None, None, None, None),
+        ("   ", None, None, None, None),
+    ];
+
+    #[test]
+    fn parses_corpus() {
+        for &(input, description, count, quantity, unit) in CORPUS {
+            let expected = Serving {
+                description: description.map(str::to_string),
+                count,
+                quantity,
+                unit,
+            };
+            assert_eq!(parse(input), expected, "serving_size {:?}", input);
+        }
+    }
+
+    #[test]
+    fn parses_numbers() {
+        assert_eq!(parse_number("2,5"), Some(2.5));
+        assert_eq!(parse_number("3/4"), Some(0.75));
+        assert_eq!(parse_number("1 1/2"), Some(1.5));
+        assert_eq!(parse_number("¾"), Some(0.75));
+        assert_eq!(parse_number("1/0"), None);
+    }
+}
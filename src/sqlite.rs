@@ -0,0 +1,407 @@
+use anyhow::{Context, Result};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::brands::Brand;
+use crate::nutrients;
+use crate::Product;
+
+const SCHEMA: &str = "
+CREATE TABLE products (
+    id INTEGER PRIMARY KEY,
+    code TEXT NOT NULL UNIQUE,
+    name TEXT,
+    generic_name TEXT,
+    brands TEXT,
+    main_category TEXT,
+    lang TEXT,
+    ingredients_text TEXT,
+    serving_size REAL,
+    serving_unit TEXT,
+    serving_description TEXT,
+    serving_count REAL,
+    additives_n INTEGER
+);
+CREATE TABLE product_names (
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    lang TEXT NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY (product_id, lang)
+) WITHOUT ROWID;
+CREATE TABLE nutrients (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    csv_column TEXT NOT NULL,
+    nutrient_group TEXT NOT NULL,
+    unit TEXT NOT NULL
+);
+CREATE TABLE product_nutrients (
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    nutrient_id INTEGER NOT NULL REFERENCES nutrients(id),
+    value_100g REAL NOT NULL,
+    PRIMARY KEY (product_id, nutrient_id)
+) WITHOUT ROWID;
+CREATE TABLE product_countries (
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    country TEXT NOT NULL,
+    PRIMARY KEY (product_id, country)
+) WITHOUT ROWID;
+CREATE TABLE brands (
+    id INTEGER PRIMARY KEY,
+    key TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL
+);
+CREATE TABLE product_brands (
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    brand_id INTEGER NOT NULL REFERENCES brands(id),
+    PRIMARY KEY (product_id, brand_id)
+) WITHOUT ROWID;
+CREATE TABLE product_tags (
+    product_id INTEGER NOT NULL REFERENCES products(id),
+    kind TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (product_id, kind, tag)
+) WITHOUT ROWID;
+CREATE VIRTUAL TABLE products_fts USING fts5(
+    name, brands,
+    content = 'products', content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+";
+
+// Created after the bulk load, which is much faster than maintaining them row by row
+const INDEXES: &str = "
+CREATE INDEX product_countries_country ON product_countries(country, product_id);
+CREATE INDEX product_brands_brand ON product_brands(brand_id, product_id);
+CREATE INDEX product_nutrients_nutrient ON product_nutrients(nutrient_id, value_100g);
+CREATE INDEX product_tags_tag ON product_tags(kind, tag);
+INSERT INTO products_fts(products_fts) VALUES ('rebuild');
+";
+
+// Child tables of `products`, copied into country subsets and cleared when a code repeats
+const PRODUCT_TABLES: [&str; 5] = [
+    "product_names",
+    "product_nutrients",
+    "product_countries",
+    "product_brands",
+    "product_tags",
+];
+
+/// Writes every product into a normalized SQLite database, one transaction per batch.
+pub struct SqliteExport {
+    path: PathBuf,
+    conn: Connection,
+    // Brand key -> row id, so brands are inserted once
+    brand_ids: HashMap<String, i64>,
+}
+
+impl SqliteExport {
+    /// Creates the database at `path`, replacing any existing file.
+    pub fn create(path: &Path) -> Result<Self> {
+        let conn = create_database(path)?;
+        {
+            let mut insert = conn.prepare("INSERT INTO nutrients (id, name, csv_column, nutrient_group, unit) VALUES (?1, ?2, ?3, ?4, ?5)")?;
+            for (slot, def) in nutrients::registry().defs().iter().enumerate() {
+                let group = serde_json::to_value(def.group)?;
+                insert.execute(params![
+                    nutrient_id(slot),
+                    def.name.as_ref(),
+                    def.column.as_ref(),
+                    group.as_str(),
+                    def.unit.symbol(),
+                ])?;
+            }
+        }
+        conn.execute_batch("BEGIN")?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            conn,
+            brand_ids: HashMap::new(),
+        })
+    }
+
+    /// Adds a product; a repeated code replaces the earlier product, like its product file.
+    pub fn add(&mut self, product: &Product, countries: &[&str], brands: &[Brand]) -> Result<()> {
+        let conn = &self.conn;
+        let existing: Option<i64> = conn
+            .prepare_cached("SELECT id FROM products WHERE code = ?1")?
+            .query_row([&product.code], |row| row.get(0))
+            .optional()?;
+        if let Some(id) = existing {
+            for table in PRODUCT_TABLES {
+                conn.prepare_cached(&format!("DELETE FROM {} WHERE product_id = ?1", table))?
+                    .execute([id])?;
+            }
+            conn.prepare_cached("DELETE FROM products WHERE id = ?1")?.execute([id])?;
+        }
+
+        conn.prepare_cached(
+            "INSERT INTO products (code, name, generic_name, brands, main_category, lang, ingredients_text,
+                serving_size, serving_unit, serving_description, serving_count, additives_n)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+        )?
+        .execute(params![
+            product.code,
+            product.product_name,
+            product.generic_name,
+            product.brands,
+            product.main_category,
+            product.lang,
+            product.ingredients_text,
+            product.serving_size,
+            product.serving_unit,
+            product.serving_description,
+            product.serving_count,
+            product.additives_n,
+        ])
+        .with_context(|| format!("Failed to insert product {} into SQLite", product.code))?;
+        let id = conn.last_insert_rowid();
+
+        let mut insert_name = conn.prepare_cached("INSERT INTO product_names (product_id, lang, name) VALUES (?1, ?2, ?3)")?;
+        for (lang, name) in &product.names {
+            insert_name.execute(params![id, lang, name])?;
+        }
+
+        let mut insert_nutrient =
+            conn.prepare_cached("INSERT INTO product_nutrients (product_id, nutrient_id, value_100g) VALUES (?1, ?2, ?3)")?;
+        for (slot, value) in product.breakdown.present() {
+            insert_nutrient.execute(params![id, nutrient_id(slot), value])?;
+        }
+
+        let mut insert_country =
+            conn.prepare_cached("INSERT OR IGNORE INTO product_countries (product_id, country) VALUES (?1, ?2)")?;
+        for country in countries {
+            insert_country.execute(params![id, country])?;
+        }
+
+        let mut insert_tag =
+            conn.prepare_cached("INSERT OR IGNORE INTO product_tags (product_id, kind, tag) VALUES (?1, ?2, ?3)")?;
+        for (kind, tags) in [("allergen", &product.allergens), ("trace", &product.traces), ("additive", &product.additives)] {
+            for tag in tags {
+                insert_tag.execute(params![id, kind, tag])?;
+            }
+        }
+
+        let mut insert_product_brand =
+            conn.prepare_cached("INSERT OR IGNORE INTO product_brands (product_id, brand_id) VALUES (?1, ?2)")?;
+        for brand in brands {
+            let brand_id = match self.brand_ids.get(&brand.key) {
+                Some(&brand_id) => brand_id,
+                None => {
+                    // The first spelling seen names the brand
+                    conn.prepare_cached("INSERT INTO brands (key, name) VALUES (?1, ?2)")?
+                        .execute(params![brand.key, brand.name])?;
+                    let brand_id = conn.last_insert_rowid();
+                    self.brand_ids.insert(brand.key.clone(), brand_id);
+                    brand_id
+                }
+            };
+            insert_product_brand.execute(params![id, brand_id])?;
+        }
+
+        Ok(())
+    }
+
+    /// Commits the products added since the last call.
+    pub fn commit_batch(&mut self) -> Result<()> {
+        self.conn
+            .execute_batch("COMMIT; BEGIN")
+            .with_context(|| format!("Failed to commit SQLite batch: {:?}", self.path))
+    }
+
+    /// Commits, builds the indexes and full-text table, then writes a
+    /// `{stem}-{country}.sqlite` subset next to the database for each of `countries`.
+    ///
+    /// Countries without products get a warning instead of an empty subset.
+    pub fn finish(self, countries: &[String]) -> Result<Vec<PathBuf>> {
+        self.conn.execute_batch("COMMIT")?;
+        self.conn
+            .execute_batch(INDEXES)
+            .with_context(|| format!("Failed to index SQLite database: {:?}", self.path))?;
+
+        let mut with_products = Vec::new();
+        {
+            let mut count = self.conn.prepare("SELECT COUNT(*) FROM product_countries WHERE country = ?1")?;
+            for country in countries {
+                let products: i64 = count.query_row([country], |row| row.get(0))?;
+                if products > 0 {
+                    with_products.push(country);
+                } else {
+                    eprintln!("   Warning: no products sold in {:?}, skipping its SQLite subset", country);
+                }
+            }
+        }
+        self.conn.close().map_err(|(_, e)| e)?;
+
+        with_products
+            .into_iter()
+            .map(|country| write_subset(&self.path, country))
+            .collect()
+    }
+}
+
+// Catalog countries that aren't ISO codes: products sold worldwide, and
+// products whose countries didn't resolve
+const NON_ISO_COUNTRIES: [&str; 2] = ["global", "unknown"];
+
+/// Parses a `--sqlite-country` argument: a catalog country, i.e. a lowercase
+/// ISO 3166-1 alpha-2 code or one of `NON_ISO_COUNTRIES`.
+pub fn parse_country(raw: &str) -> Result<String, String> {
+    let country = raw.trim().to_lowercase();
+    let alpha_2 = country.len() == 2 && country.chars().all(|c| c.is_ascii_lowercase());
+    if alpha_2 || NON_ISO_COUNTRIES.contains(&country.as_str()) {
+        Ok(country)
+    } else {
+        Err(format!("expected an ISO 3166-1 alpha-2 code such as `fr`, `global` or `unknown`, got {:?}", raw))
+    }
+}
+
+fn nutrient_id(slot: usize) -> i64 {
+    slot as i64 + 1
+}
+
+fn create_database(path: &Path) -> Result<Connection> {
+    if path.exists() {
+        fs::remove_file(path).with_context(|| format!("Failed to remove old SQLite database: {:?}", path))?;
+    }
+    let conn = Connection::open(path).with_context(|| format!("Failed to create SQLite database: {:?}", path))?;
+    // The file is rebuilt from scratch on failure, so durability is not needed
+    conn.execute_batch("PRAGMA journal_mode = OFF; PRAGMA synchronous = OFF;")?;
+    conn.execute_batch(SCHEMA)
+        .with_context(|| format!("Failed to create SQLite schema: {:?}", path))?;
+    Ok(conn)
+}
+
+fn subset_path(path: &Path, country: &str) -> PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("products");
+    let extension = path.extension().and_then(|s| s.to_str()).unwrap_or("sqlite");
+    path.with_file_name(format!("{}-{}.{}", stem, country, extension))
+}
+
+/// Copies the products of one country, with all of their rows, into a database of their own.
+fn write_subset(full_path: &Path, country: &str) -> Result<PathBuf> {
+    let path = subset_path(full_path, country);
+    let conn = create_database(&path)?;
+    conn.execute("ATTACH DATABASE ?1 AS source", [full_path.to_string_lossy()])
+        .with_context(|| format!("Failed to attach SQLite database: {:?}", full_path))?;
+
+    let in_country = "SELECT product_id FROM source.product_countries WHERE country = ?1";
+    conn.execute_batch("BEGIN")?;
+    // Parents first, for the foreign keys
+    conn.execute_batch("INSERT INTO nutrients SELECT * FROM source.nutrients")?;
+    conn.execute(
+        &format!(
+            "INSERT INTO brands SELECT * FROM source.brands
+             WHERE id IN (SELECT brand_id FROM source.product_brands WHERE product_id IN ({in_country}))"
+        ),
+        [country],
+    )?;
+    conn.execute(&format!("INSERT INTO products SELECT * FROM source.products WHERE id IN ({in_country})"), [country])?;
+    for table in PRODUCT_TABLES {
+        conn.execute(
+            &format!("INSERT INTO {table} SELECT * FROM source.{table} WHERE product_id IN ({in_country})"),
+            [country],
+        )?;
+    }
+    conn.execute_batch("COMMIT")?;
+    conn.execute_batch("DETACH DATABASE source")?;
+    conn.execute_batch(INDEXES)
+        .with_context(|| format!("Failed to index SQLite database: {:?}", path))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::brands;
+
+    fn count(conn: &Connection, sql: &str) -> i64 {
+        conn.query_row(sql, [], |row| row.get(0)).unwrap()
+    }
+
+    fn fts_codes(conn: &Connection, query: &str) -> Vec<String> {
+        let mut statement = conn
+            .prepare("SELECT p.code FROM products_fts f JOIN products p ON p.id = f.rowid WHERE products_fts MATCH ?1 ORDER BY p.code")
+            .unwrap();
+        statement.query_map([query], |row| row.get(0)).unwrap().map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn round_trips_products_subsets_and_full_text() {
+        let dir = std::env::temp_dir().join(format!("sqlite-export-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("products.sqlite");
+
+        let mut export = SqliteExport::create(&path).unwrap();
+        let mut brulee = Product::named("1", "Crème brûlée");
+        brulee.brands = Some("Nestlé".to_string());
+        brulee.allergens = vec!["milk".to_string(), "eggs".to_string()];
+        brulee.breakdown.set("fat", Some(12.0));
+        export.add(&brulee, &["fr", "de"], &brands::canonicalize("Nestlé")).unwrap();
+        export.add(&Product::named("2", "Apfelstrudel"), &["de"], &[]).unwrap();
+        export.commit_batch().unwrap();
+
+        // A repeated code replaces the product and all of its rows
+        let mut replaced = Product::named("1", "Crème caramel");
+        replaced.brands = Some("Nestlé".to_string());
+        export.add(&replaced, &["fr"], &brands::canonicalize("NESTLE")).unwrap();
+        let subsets = export.finish(&["fr".to_string(), "it".to_string()]).unwrap();
+        assert_eq!(subsets, [dir.join("products-fr.sqlite")]);
+        assert!(!dir.join("products-it.sqlite").exists());
+
+        let full = Connection::open(&path).unwrap();
+        assert_eq!(count(&full, "SELECT COUNT(*) FROM products"), 2);
+        assert_eq!(count(&full, "SELECT COUNT(*) FROM product_countries"), 2);
+        assert_eq!(count(&full, "SELECT COUNT(*) FROM product_tags"), 0);
+        assert_eq!(count(&full, "SELECT COUNT(*) FROM product_nutrients"), 0);
+        assert_eq!(count(&full, "SELECT COUNT(*) FROM brands"), 1);
+        assert_eq!(count(&full, "SELECT COUNT(*) FROM nutrients"), nutrients::registry().defs().len() as i64);
+        assert_eq!(fts_codes(&full, "creme"), ["1"]);
+        assert!(fts_codes(&full, "brulee").is_empty());
+        assert_eq!(fts_codes(&full, "nestle"), ["1"]);
+
+        let fr = Connection::open(dir.join("products-fr.sqlite")).unwrap();
+        assert_eq!(count(&fr, "SELECT COUNT(*) FROM products"), 1);
+        assert_eq!(count(&fr, "SELECT COUNT(*) FROM product_brands"), 1);
+        assert_eq!(count(&fr, "SELECT COUNT(*) FROM nutrients"), nutrients::registry().defs().len() as i64);
+        assert_eq!(fts_codes(&fr, "caramel"), ["1"]);
+        assert!(fts_codes(&fr, "apfelstrudel").is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn subset_countries_are_alpha_2_codes_or_catalog_pseudo_countries() {
+        assert_eq!(parse_country(" FR ").unwrap(), "fr");
+        assert_eq!(parse_country("global").unwrap(), "global");
+        assert_eq!(parse_country("Unknown").unwrap(), "unknown");
+        assert!(parse_country("fra").is_err());
+        assert!(parse_country("f1").is_err());
+        assert!(parse_country("world").is_err());
+    }
+
+    #[test]
+    fn builds_subsets_for_global_and_unknown_products() {
+        let dir = std::env::temp_dir().join(format!("sqlite-pseudo-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("products.sqlite");
+
+        let mut export = SqliteExport::create(&path).unwrap();
+        export.add(&Product::named("1", "Water"), &["global", "fr"], &[]).unwrap();
+        export.add(&Product::named("2", "Mystery"), &["unknown"], &[]).unwrap();
+        let subsets = export.finish(&["global".to_string(), "unknown".to_string()]).unwrap();
+        assert_eq!(subsets, [dir.join("products-global.sqlite"), dir.join("products-unknown.sqlite")]);
+
+        let global = Connection::open(dir.join("products-global.sqlite")).unwrap();
+        assert_eq!(count(&global, "SELECT COUNT(*) FROM products"), 1);
+        assert_eq!(fts_codes(&global, "water"), ["1"]);
+        let unknown = Connection::open(dir.join("products-unknown.sqlite")).unwrap();
+        assert_eq!(fts_codes(&unknown, "mystery"), ["2"]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}